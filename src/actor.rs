@@ -9,9 +9,10 @@
 use crate::wallet::WalletSnapshot;
 
 use super::{
-    wallet::Wallet, ActorEvent, Outcome, ReceivedCredit, ReplicaValidator, TernaryResult,
-    TransferInitiated, TransferRegistrationSent, TransferValidated, TransferValidationReceived,
-    TransfersSynched,
+    payload::{actor_credit_payload, actor_debit_payload},
+    wallet::Wallet,
+    ActorEvent, Outcome, ReceivedCredit, ReplicaValidator, TernaryResult, TransferInitiated,
+    TransferRegistrationSent, TransferValidated, TransferValidationReceived, TransfersSynched,
 };
 use crdts::Dot;
 use itertools::Itertools;
@@ -162,19 +163,13 @@ impl<V: ReplicaValidator> Actor<V> {
             msg,
         };
 
-        let signed_debit = match bincode::serialize(&debit) {
-            Err(_) => return Err(Error::NetworkOther("Could not serialise debit".into())),
-            Ok(data) => SignedDebit {
-                debit,
-                actor_signature: self.keypair.sign(&data),
-            },
+        let signed_debit = SignedDebit {
+            actor_signature: self.keypair.sign(&actor_debit_payload(&debit)?),
+            debit,
         };
-        let signed_credit = match bincode::serialize(&credit) {
-            Err(_) => return Err(Error::NetworkOther("Could not serialise credit".into())),
-            Ok(data) => SignedCredit {
-                credit,
-                actor_signature: self.keypair.sign(&data),
-            },
+        let signed_credit = SignedCredit {
+            actor_signature: self.keypair.sign(&actor_credit_payload(&credit)?),
+            credit,
         };
 
         Outcome::success(TransferInitiated {
@@ -0,0 +1,230 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    payload::{signing_payload, PayloadFormat},
+    proofs::{combine_shares, to_threshold_shares},
+    wallet::WalletSnapshot,
+    Codec,
+};
+use serde::{Deserialize, Serialize};
+use sn_data_types::{Error, Money, PublicKey, Result, Signature, SignatureShare};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use threshold_crypto::PublicKeySet;
+use xor_name::XorName;
+
+/// A compact summary of the state of a wallet, gossiped between
+/// Replicas for anti-entropy instead of the full history.
+/// Peers compare summaries to cheaply detect divergence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletSummary {
+    /// The balance of the wallet.
+    pub balance: Money,
+    /// The next debit version, i.e. the number of debits applied.
+    pub next_debit: u64,
+    /// A digest of the ids of all credits applied.
+    pub credits_digest: XorName,
+}
+
+impl TryFrom<&WalletSnapshot> for WalletSummary {
+    type Error = Error;
+
+    fn try_from(snapshot: &WalletSnapshot) -> Result<Self> {
+        // The ids are sorted, so that the digest doesn't depend on the order of the set.
+        let mut credit_ids: Vec<_> = snapshot.credit_ids.iter().collect();
+        credit_ids.sort();
+        let bytes =
+            bincode::serialize(&credit_ids).map_err(|e| Error::NetworkOther(e.to_string()))?;
+        Ok(Self {
+            balance: snapshot.balance,
+            next_debit: snapshot.debit_version,
+            credits_digest: XorName::from_content(&bytes),
+        })
+    }
+}
+
+/// A wallet summary, with the signature share of the Replica summarising it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedWalletSummary {
+    /// The summary.
+    pub summary: WalletSummary,
+    /// The signature share of the Replica.
+    pub replica_sig: SignatureShare,
+}
+
+/// Verifies that the summary was signed by the Replica at the index
+/// of the signature share, under the given codec and payload format.
+pub fn verify_wallet_summary<C: Codec>(
+    codec: &C,
+    signed: &SignedWalletSummary,
+    peer_replicas: &PublicKeySet,
+    format: &PayloadFormat,
+) -> Result<()> {
+    let bytes = signing_payload(codec, format, &signed.summary)?;
+    let key_share = peer_replicas.public_key_share(signed.replica_sig.index);
+    if key_share.verify(&signed.replica_sig.share, &bytes) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+/// The balance of a wallet as of a debit counter,
+/// i.e. what the Replicas attest to in a `BalanceAttestation`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceClaim {
+    /// The wallet.
+    pub wallet: PublicKey,
+    /// The balance of the wallet.
+    pub balance: Money,
+    /// The counter of the last debit applied when the balance was attested.
+    pub as_of_counter: u64,
+}
+
+/// A balance, attested by a group of Replicas, that a client
+/// can present to third parties, see `verify_balance_attestation`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceAttestation {
+    /// The attested balance.
+    pub claim: BalanceClaim,
+    /// The signature of the group over the claim.
+    pub group_sig: Signature,
+    /// The key set of the group.
+    pub group: PublicKeySet,
+}
+
+/// Combines the signature shares of a quorum of the Replicas
+/// over the claim into an attestation, under the given codec and payload format.
+pub fn combine_balance_attestation<C: Codec>(
+    codec: &C,
+    claim: BalanceClaim,
+    shares: BTreeMap<usize, SignatureShare>,
+    peer_replicas: &PublicKeySet,
+    format: &PayloadFormat,
+) -> Result<BalanceAttestation> {
+    let bytes = signing_payload(codec, format, &claim)?;
+    let signature = combine_shares(peer_replicas, &to_threshold_shares(shares), &bytes)?;
+    Ok(BalanceAttestation {
+        claim,
+        group_sig: Signature::Bls(signature),
+        group: peer_replicas.clone(),
+    })
+}
+
+/// Verifies that the attestation was signed by the group of
+/// one of the trusted keys, under the given codec and payload format.
+pub fn verify_balance_attestation<C: Codec>(
+    codec: &C,
+    attestation: &BalanceAttestation,
+    trusted_keys: &[PublicKey],
+    format: &PayloadFormat,
+) -> Result<()> {
+    let bytes = signing_payload(codec, format, &attestation.claim)?;
+    if trusted_keys
+        .iter()
+        .any(|key| key.verify(&attestation.group_sig, &bytes).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{BincodeCodec, KeyIndex, ReplicaSigning};
+    use sn_data_types::CreditId;
+    use std::collections::HashSet;
+    use threshold_crypto::SecretKey;
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn identical_wallets_have_matching_summaries() -> Result<()> {
+        let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let replica = ReplicaSigning::new(
+            sk_set.secret_key_share(0),
+            KeyIndex(0),
+            sk_set.public_keys(),
+        );
+        let ids: Vec<CreditId> = (0..10).map(|_| XorName::random()).collect();
+        let snapshot = get_snapshot(100, 2, ids.clone());
+        let same = get_snapshot(100, 2, ids.into_iter().rev().collect());
+
+        let signed = replica.sign_wallet_summary(&snapshot)?.unwrap();
+        let signed_same = replica.sign_wallet_summary(&same)?.unwrap();
+
+        assert_eq!(signed.summary, signed_same.summary);
+        verify_wallet_summary(
+            &BincodeCodec,
+            &signed,
+            &sk_set.public_keys(),
+            replica.payload_format(),
+        )
+    }
+
+    #[test]
+    fn combined_balance_attestation_verifies() -> Result<()> {
+        let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let peer_replicas = sk_set.public_keys();
+        let wallet = PublicKey::from(SecretKey::random().public_key());
+        let balance = Money::from_nano(100);
+        let shares = (0..=sk_set.threshold())
+            .map(|index| {
+                let replica = ReplicaSigning::new(
+                    sk_set.secret_key_share(index),
+                    KeyIndex(index),
+                    peer_replicas.clone(),
+                );
+                let share = replica
+                    .sign_balance_attestation(balance, wallet, 2)?
+                    .ok_or_else(|| Error::from("Expected a signature share"))?;
+                Ok((index, share))
+            })
+            .collect::<Result<BTreeMap<_, _>>>()?;
+        let claim = BalanceClaim {
+            wallet,
+            balance,
+            as_of_counter: 2,
+        };
+        let format = PayloadFormat::default();
+
+        let mut attestation =
+            combine_balance_attestation(&BincodeCodec, claim, shares, &peer_replicas, &format)?;
+
+        let trusted_keys = [PublicKey::Bls(peer_replicas.public_key())];
+        verify_balance_attestation(&BincodeCodec, &attestation, &trusted_keys, &format)?;
+        attestation.claim.balance = Money::from_nano(1000);
+        let result =
+            verify_balance_attestation(&BincodeCodec, &attestation, &trusted_keys, &format);
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn divergent_wallets_have_different_summaries() -> Result<()> {
+        let ids: Vec<CreditId> = (0..10).map(|_| XorName::random()).collect();
+        let snapshot = get_snapshot(100, 2, ids.clone());
+        let missing_credit = get_snapshot(100, 2, ids[1..].to_vec());
+
+        let summary = WalletSummary::try_from(&snapshot)?;
+        let divergent = WalletSummary::try_from(&missing_credit)?;
+
+        assert_ne!(summary.credits_digest, divergent.credits_digest);
+        Ok(())
+    }
+
+    fn get_snapshot(balance: u64, debit_version: u64, credit_ids: Vec<CreditId>) -> WalletSnapshot {
+        WalletSnapshot {
+            balance: Money::from_nano(balance),
+            debit_version,
+            credit_ids: credit_ids.into_iter().collect::<HashSet<_>>(),
+        }
+    }
+}
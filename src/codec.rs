@@ -0,0 +1,43 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::serialize_into;
+use serde::{de::DeserializeOwned, Serialize};
+use sn_data_types::{Error, Result};
+use std::fmt::Debug;
+
+/// The encoding of the values that Replicas sign and verify.
+/// The Replicas signing and the Replicas verifying must use the same codec.
+pub trait Codec: Clone + Debug + Send + Sync {
+    /// Serialises the value into the buffer, replacing its contents.
+    fn serialize_into<T: Serialize + ?Sized>(&self, buf: &mut Vec<u8>, value: &T) -> Result<()>;
+
+    /// Deserialises a value from the bytes.
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T>;
+
+    /// Serialises the value.
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.serialize_into(&mut buf, value)?;
+        Ok(buf)
+    }
+}
+
+/// The default codec, encoding with bincode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn serialize_into<T: Serialize + ?Sized>(&self, buf: &mut Vec<u8>, value: &T) -> Result<()> {
+        serialize_into(buf, value)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+        bincode::deserialize(bytes).map_err(|e| Error::NetworkOther(e.to_string()))
+    }
+}
@@ -0,0 +1,103 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use sn_data_types::{Credit, Debit, Error, Result, SignedTransfer};
+
+/// Checks that the debit and credit of the transfer correspond to each other,
+/// i.e. the credit id is derived from the debit, the amounts are equal,
+/// and it is not a transfer to self. No signatures are verified.
+pub fn check_transfer_consistency(transfer: &SignedTransfer) -> Result<()> {
+    check_consistency(&transfer.debit.debit, &transfer.credit.credit)
+}
+
+/// Checks that the debit and credit correspond to each other.
+pub(crate) fn check_consistency(debit: &Debit, credit: &Credit) -> Result<()> {
+    if debit.sender() == credit.recipient() {
+        Err(Error::from("Sender and recipient are the same."))
+    } else if credit.id() != &debit.credit_id()? {
+        Err(Error::from("The credit does not correspond to the debit."))
+    } else if credit.amount() != debit.amount() {
+        Err(Error::from("Amounts must be equal."))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crdts::Dot;
+    use sn_data_types::{Keypair, Money, PublicKey, SignedCredit, SignedDebit};
+    use threshold_crypto::SecretKey;
+
+    #[test]
+    fn accepts_consistent_transfer() -> Result<()> {
+        check_transfer_consistency(&get_signed_transfer(get_random_pk())?)
+    }
+
+    #[test]
+    fn rejects_mismatched_credit_id() -> Result<()> {
+        let mut transfer = get_signed_transfer(get_random_pk())?;
+        transfer.credit.credit.id = Default::default();
+        assert_rejected(&transfer, "The credit does not correspond to the debit.");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_mismatched_amount() -> Result<()> {
+        let mut transfer = get_signed_transfer(get_random_pk())?;
+        transfer.credit.credit.amount = Money::from_nano(11);
+        assert_rejected(&transfer, "Amounts must be equal.");
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_self_transfer() -> Result<()> {
+        let mut transfer = get_signed_transfer(get_random_pk())?;
+        transfer.credit.credit.recipient = transfer.debit.sender();
+        assert_rejected(&transfer, "Sender and recipient are the same.");
+        Ok(())
+    }
+
+    fn assert_rejected(transfer: &SignedTransfer, expected: &str) {
+        match check_transfer_consistency(transfer) {
+            Err(error) => assert_eq!(error.to_string(), Error::from(expected).to_string()),
+            Ok(()) => panic!("Expected the transfer to be rejected"),
+        }
+    }
+
+    fn get_signed_transfer(recipient: PublicKey) -> Result<SignedTransfer> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let debit = Debit {
+            id: Dot::new(keypair.public_key(), 0),
+            amount: Money::from_nano(10),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient,
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        let debit_bytes = bincode::serialize(&debit).map_err(|e| Error::from(e.to_string()))?;
+        let credit_bytes = bincode::serialize(&credit).map_err(|e| Error::from(e.to_string()))?;
+        Ok(SignedTransfer {
+            debit: SignedDebit {
+                debit,
+                actor_signature: keypair.sign(&debit_bytes),
+            },
+            credit: SignedCredit {
+                credit,
+                actor_signature: keypair.sign(&credit_bytes),
+            },
+        })
+    }
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+}
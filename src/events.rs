@@ -0,0 +1,328 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{Deserialize, Serialize};
+use sn_data_types::{CreditId, Error, ReplicaEvent, Result};
+use std::collections::BTreeMap;
+
+/// The current version of the persisted event format.
+pub const EVENT_VERSION: u16 = 1;
+
+/// A replica event tagged with the version of its format, for persisting
+/// event logs that survive upgrades of the format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionedEvent {
+    /// The version of the format of the event.
+    pub version: u16,
+    /// The event.
+    pub event: ReplicaEvent,
+}
+
+impl VersionedEvent {
+    /// Tags the event with the current version.
+    pub fn new(event: ReplicaEvent) -> Self {
+        Self {
+            version: EVENT_VERSION,
+            event,
+        }
+    }
+
+    /// Serialises the versioned event.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| Error::NetworkOther(e.to_string()))
+    }
+
+    /// Deserialises a versioned event.
+    /// The version is read first, and an unknown version is rejected
+    /// instead of parsing the rest of the bytes by the wrong format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let version: u16 =
+            bincode::deserialize(bytes).map_err(|e| Error::NetworkOther(e.to_string()))?;
+        if version != EVENT_VERSION {
+            return Err(Error::NetworkOther(format!(
+                "Unknown event version: {}",
+                version
+            )));
+        }
+        bincode::deserialize(bytes).map_err(|e| Error::NetworkOther(e.to_string()))
+    }
+}
+
+/// The stage of a debit, in the order the stages are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DebitStage {
+    /// The debit was validated by a Replica.
+    Validated,
+    /// The debit was registered with a proof of agreement.
+    Registered,
+}
+
+/// A canonical sort key for replica events, f.ex. for merging
+/// events received out of order during sync.
+/// Known groups order first, then credits by id, then debits by
+/// counter and stage. Since credits precede the debits,
+/// the sorted events can be replayed without running out of balance.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EventKey {
+    /// A known group, by the bytes of its public key.
+    KnownGroup(Vec<u8>),
+    /// A propagated credit, by its id.
+    Credit(CreditId),
+    /// A debit, by its counter and stage.
+    Debit(u64, DebitStage),
+}
+
+/// The canonical sort key of the event.
+pub fn sort_key(event: &ReplicaEvent) -> EventKey {
+    match event {
+        ReplicaEvent::KnownGroupAdded(e) => {
+            EventKey::KnownGroup(e.group.public_key().to_bytes().to_vec())
+        }
+        ReplicaEvent::TransferPropagated(e) => EventKey::Credit(*e.credit_proof.id()),
+        ReplicaEvent::TransferValidated(e) => {
+            EventKey::Debit(e.signed_debit.debit.id.counter, DebitStage::Validated)
+        }
+        ReplicaEvent::TransferRegistered(e) => EventKey::Debit(
+            e.transfer_proof.signed_debit.debit.id.counter,
+            DebitStage::Registered,
+        ),
+    }
+}
+
+/// Merges two logs of events, f.ex. of two Replicas, into one log in canonical order,
+/// see `sort_key`. Events of the same transfer, i.e. differing only in
+/// the signatures of the Replicas, are deduplicated, keeping the first.
+/// Events of different transfers at the same key are a conflict, and error.
+pub fn merge_event_logs(a: Vec<ReplicaEvent>, b: Vec<ReplicaEvent>) -> Result<Vec<ReplicaEvent>> {
+    let mut merged = BTreeMap::new();
+    for event in a.into_iter().chain(b) {
+        let key = sort_key(&event);
+        match merged.get(&key) {
+            Some(existing) if same_transfer(existing, &event) => (),
+            Some(_) => {
+                return Err(Error::NetworkOther(format!(
+                    "Conflicting events at {:?}",
+                    key
+                )))
+            }
+            None => {
+                let _ = merged.insert(key, event);
+            }
+        }
+    }
+    Ok(merged.into_iter().map(|(_, event)| event).collect())
+}
+
+/// Whether the events are of the same transfer, regardless of who signed them.
+fn same_transfer(a: &ReplicaEvent, b: &ReplicaEvent) -> bool {
+    match (a, b) {
+        (ReplicaEvent::TransferValidated(a), ReplicaEvent::TransferValidated(b)) => {
+            a.signed_debit == b.signed_debit && a.signed_credit == b.signed_credit
+        }
+        (ReplicaEvent::TransferRegistered(a), ReplicaEvent::TransferRegistered(b)) => {
+            a.transfer_proof.signed_debit == b.transfer_proof.signed_debit
+                && a.transfer_proof.signed_credit == b.transfer_proof.signed_credit
+        }
+        (ReplicaEvent::TransferPropagated(a), ReplicaEvent::TransferPropagated(b)) => {
+            a.credit_proof.signed_credit == b.credit_proof.signed_credit
+        }
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crdts::Dot;
+    use sn_data_types::{
+        Credit, CreditAgreementProof, Debit, Keypair, KnownGroupAdded, Money, PublicKey, Signature,
+        SignatureShare, SignedCredit, SignedDebit, TransferAgreementProof, TransferPropagated,
+        TransferRegistered, TransferValidated,
+    };
+    use threshold_crypto::{SecretKey, SecretKeySet};
+    use xor_name::XorName;
+
+    #[test]
+    fn sorts_shuffled_events_into_replay_order() {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let group = get_group_added(&sk_set);
+        let credit_a = get_propagated(&sk_set, XorName([1; 32]));
+        let credit_b = get_propagated(&sk_set, XorName([2; 32]));
+        let validated_0 = get_validated(&sk_set, &keypair, 0);
+        let registered_0 = get_registered(&sk_set, &keypair, 0);
+        let validated_1 = get_validated(&sk_set, &keypair, 1);
+        let registered_1 = get_registered(&sk_set, &keypair, 1);
+        let mut events = vec![
+            registered_1.clone(),
+            credit_b.clone(),
+            validated_0.clone(),
+            group.clone(),
+            registered_0.clone(),
+            validated_1.clone(),
+            credit_a.clone(),
+        ];
+
+        events.sort_by_key(sort_key);
+
+        assert_eq!(
+            events,
+            vec![
+                group,
+                credit_a,
+                credit_b,
+                validated_0,
+                registered_0,
+                validated_1,
+                registered_1
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_event_logs_into_replay_order() -> Result<()> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let group = get_group_added(&sk_set);
+        let credit = get_propagated(&sk_set, XorName([1; 32]));
+        let validated = get_validated(&sk_set, &keypair, 0);
+        let registered = get_registered(&sk_set, &keypair, 0);
+        // The same validation, as signed by another Replica.
+        let mut validated_elsewhere = validated.clone();
+        if let ReplicaEvent::TransferValidated(e) = &mut validated_elsewhere {
+            e.replica_debit_sig = get_share(&sk_set);
+            e.replica_credit_sig = get_share(&sk_set);
+        }
+
+        let merged = merge_event_logs(
+            vec![registered.clone(), group.clone(), validated.clone()],
+            vec![validated_elsewhere, credit.clone(), group.clone()],
+        )?;
+
+        assert_eq!(merged, vec![group, credit, validated, registered]);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_conflicting_event_logs() {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let validated = get_validated(&sk_set, &Keypair::new_ed25519(&mut rand::thread_rng()), 0);
+        let other = get_validated(&sk_set, &Keypair::new_ed25519(&mut rand::thread_rng()), 0);
+
+        let result = merge_event_logs(vec![validated], vec![other]);
+
+        assert!(matches!(result, Err(Error::NetworkOther(_))));
+    }
+
+    #[test]
+    fn round_trips_versioned_event() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let event = VersionedEvent::new(get_propagated(&sk_set, XorName([1; 32])));
+
+        let bytes = event.to_bytes()?;
+
+        assert_eq!(VersionedEvent::from_bytes(&bytes)?, event);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_event_version() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let event = VersionedEvent {
+            version: EVENT_VERSION + 1,
+            event: get_group_added(&sk_set),
+        };
+
+        let result = VersionedEvent::from_bytes(&event.to_bytes()?);
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(msg, "Unknown event version: 2"),
+            _ => panic!("Expected an unknown version, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    fn get_group_added(sk_set: &SecretKeySet) -> ReplicaEvent {
+        ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: sk_set.public_keys(),
+        })
+    }
+
+    fn get_propagated(sk_set: &SecretKeySet, id: CreditId) -> ReplicaEvent {
+        let signed_credit = get_signed_credit(id);
+        ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof: CreditAgreementProof {
+                signed_credit,
+                debiting_replicas_sig: get_signature(),
+                debiting_replicas_keys: sk_set.public_keys(),
+            },
+            crediting_replica_sig: get_share(sk_set),
+            crediting_replica_keys: get_random_pk(),
+        })
+    }
+
+    fn get_validated(sk_set: &SecretKeySet, keypair: &Keypair, counter: u64) -> ReplicaEvent {
+        ReplicaEvent::TransferValidated(TransferValidated {
+            signed_debit: get_signed_debit(keypair, counter),
+            signed_credit: get_signed_credit(Default::default()),
+            replica_debit_sig: get_share(sk_set),
+            replica_credit_sig: get_share(sk_set),
+            replicas: sk_set.public_keys(),
+        })
+    }
+
+    fn get_registered(sk_set: &SecretKeySet, keypair: &Keypair, counter: u64) -> ReplicaEvent {
+        ReplicaEvent::TransferRegistered(TransferRegistered {
+            transfer_proof: TransferAgreementProof {
+                signed_debit: get_signed_debit(keypair, counter),
+                debit_sig: get_signature(),
+                signed_credit: get_signed_credit(Default::default()),
+                credit_sig: get_signature(),
+                debiting_replicas_keys: sk_set.public_keys(),
+            },
+        })
+    }
+
+    fn get_signed_debit(keypair: &Keypair, counter: u64) -> SignedDebit {
+        SignedDebit {
+            debit: Debit {
+                id: Dot::new(keypair.public_key(), counter),
+                amount: Money::from_nano(10),
+            },
+            actor_signature: get_signature(),
+        }
+    }
+
+    fn get_signed_credit(id: CreditId) -> SignedCredit {
+        SignedCredit {
+            credit: Credit {
+                id,
+                recipient: get_random_pk(),
+                amount: Money::from_nano(10),
+                msg: "credit".to_string(),
+            },
+            actor_signature: get_signature(),
+        }
+    }
+
+    // The signatures are never verified when sorting.
+    fn get_signature() -> Signature {
+        Signature::Bls(SecretKey::random().sign(b"event"))
+    }
+
+    fn get_share(sk_set: &SecretKeySet) -> SignatureShare {
+        SignatureShare {
+            index: 0,
+            share: sk_set.secret_key_share(0).sign(b"event"),
+        }
+    }
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+}
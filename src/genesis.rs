@@ -6,9 +6,47 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use sn_data_types::{Credit, CreditAgreementProof, Error, Money, PublicKey, Result, SignedCredit};
+use super::{
+    payload::{actor_credit_payload, signing_payload, PayloadFormat},
+    proofs::combine_shares,
+    verify::verify_credit_proof,
+    BincodeCodec,
+};
+use sn_data_types::{
+    Credit, CreditAgreementProof, CreditId, Error, Money, PublicKey, Result, Signature,
+    SignedCredit,
+};
 use std::collections::BTreeMap;
-use threshold_crypto::SecretKeySet;
+use threshold_crypto::{PublicKeySet, SecretKeySet};
+
+/// Assembles the genesis proof of a new network from a credit and
+/// replica signature produced elsewhere, f.ex. by an offline quorum.
+/// Both the actor signature of the credit and the replica signature
+/// must be of the combined key of the peers.
+pub fn assemble_genesis(
+    signed_credit: SignedCredit,
+    debiting_replicas_sig: Signature,
+    peer_replicas: PublicKeySet,
+) -> Result<CreditAgreementProof> {
+    // Genesis always has the canonical default credit id.
+    if signed_credit.id() != &CreditId::default() {
+        return Err(Error::InvalidOperation);
+    }
+    let peer_key = PublicKey::Bls(peer_replicas.public_key());
+    peer_key
+        .verify(
+            &signed_credit.actor_signature,
+            actor_credit_payload(&signed_credit.credit)?,
+        )
+        .map_err(|_| Error::InvalidSignature)?;
+    let proof = CreditAgreementProof {
+        signed_credit,
+        debiting_replicas_sig,
+        debiting_replicas_keys: peer_replicas,
+    };
+    verify_credit_proof(&proof, &[peer_key])?;
+    Ok(proof)
+}
 
 /// Produces a genesis balance for a new network.
 pub fn get_genesis(balance: u64, id: PublicKey) -> Result<CreditAgreementProof> {
@@ -34,25 +72,30 @@ pub fn get_genesis(balance: u64, id: PublicKey) -> Result<CreditAgreementProof>
 
     let serialised_credit =
         bincode::serialize(&credit).map_err(|e| Error::NetworkOther(e.to_string()))?;
-    let credit_sig_share = secret_key.sign(serialised_credit);
+    let credit_sig_share = secret_key.sign(&serialised_credit);
     let mut credit_sig_shares = BTreeMap::new();
     let _ = credit_sig_shares.insert(0, credit_sig_share);
     // Combine shares to produce the main signature.
-    let actor_signature = sn_data_types::Signature::Bls(
-        peer_replicas
-            .combine_signatures(&credit_sig_shares)
-            .map_err(|e| Error::NetworkOther(e.to_string()))?,
-    );
+    let actor_signature = sn_data_types::Signature::Bls(combine_shares(
+        &peer_replicas,
+        &credit_sig_shares,
+        &serialised_credit,
+    )?);
 
     let signed_credit = SignedCredit {
         credit,
         actor_signature,
     };
-    let debiting_replicas_sig = sn_data_types::Signature::Bls(
-        peer_replicas
-            .combine_signatures(&credit_sig_shares)
-            .map_err(|e| Error::NetworkOther(e.to_string()))?,
-    );
+    // The replicas sign the signed credit, as verified on propagation.
+    let serialised_signed_credit =
+        signing_payload(&BincodeCodec, &PayloadFormat::default(), &signed_credit)?;
+    let mut proof_sig_shares = BTreeMap::new();
+    let _ = proof_sig_shares.insert(0, secret_key.sign(&serialised_signed_credit));
+    let debiting_replicas_sig = sn_data_types::Signature::Bls(combine_shares(
+        &peer_replicas,
+        &proof_sig_shares,
+        &serialised_signed_credit,
+    )?);
 
     Ok(CreditAgreementProof {
         signed_credit,
@@ -60,3 +103,59 @@ pub fn get_genesis(balance: u64, id: PublicKey) -> Result<CreditAgreementProof>
         debiting_replicas_keys: peer_replicas,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use threshold_crypto::SecretKey;
+
+    #[test]
+    fn assembles_externally_signed_genesis() -> Result<()> {
+        let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let (signed_credit, debiting_replicas_sig) = sign_offline(&sk_set)?;
+
+        let proof = assemble_genesis(signed_credit, debiting_replicas_sig, sk_set.public_keys())?;
+
+        verify_credit_proof(&proof, &[PublicKey::Bls(sk_set.public_keys().public_key())])
+    }
+
+    #[test]
+    fn rejects_genesis_signed_by_others() -> Result<()> {
+        let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let (signed_credit, _) = sign_offline(&sk_set)?;
+        let forged = Signature::Bls(SecretKey::random().sign(b"genesis"));
+
+        let result = assemble_genesis(signed_credit, forged, sk_set.public_keys());
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    /// Signs the genesis credit and proof with a quorum of the key shares, as offline.
+    fn sign_offline(sk_set: &SecretKeySet) -> Result<(SignedCredit, Signature)> {
+        let credit = Credit {
+            id: Default::default(),
+            amount: Money::from_nano(1000),
+            recipient: PublicKey::from(SecretKey::random().public_key()),
+            msg: "genesis".to_string(),
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: sign_with_quorum(sk_set, &actor_credit_payload(&credit)?)?,
+            credit,
+        };
+        let payload = signing_payload(&BincodeCodec, &PayloadFormat::default(), &signed_credit)?;
+        let debiting_replicas_sig = sign_with_quorum(sk_set, &payload)?;
+        Ok((signed_credit, debiting_replicas_sig))
+    }
+
+    fn sign_with_quorum(sk_set: &SecretKeySet, data: &[u8]) -> Result<Signature> {
+        let shares: BTreeMap<_, _> = (0..=sk_set.threshold())
+            .map(|i| (i, sk_set.secret_key_share(i).sign(data)))
+            .collect();
+        Ok(Signature::Bls(combine_shares(
+            &sk_set.public_keys(),
+            &shares,
+            data,
+        )?))
+    }
+}
@@ -26,16 +26,63 @@
 )]
 
 mod actor;
+mod attestation;
+mod clock;
+mod codec;
+mod consistency;
+mod events;
 mod genesis;
+mod money;
+mod multisig;
+mod payload;
+mod proofs;
+mod read_only_replica;
+#[cfg(feature = "testing")]
+mod replay;
 mod replica;
 mod replica_signing;
+#[cfg(feature = "simulated-payouts")]
+mod simulated_replica;
+mod verify;
 mod wallet;
 mod wallet_replica;
 
+#[cfg(feature = "simulated-payouts")]
+pub use self::simulated_replica::SimulatedReplica;
 pub use self::{
-    actor::Actor as TransferActor, genesis::get_genesis, replica::Replica as TransferReplica,
-    replica_signing::ReplicaSigning, wallet::Wallet, wallet_replica::WalletReplica,
+    actor::Actor as TransferActor,
+    attestation::{
+        combine_balance_attestation, verify_balance_attestation, verify_wallet_summary,
+        BalanceAttestation, BalanceClaim, SignedWalletSummary, WalletSummary,
+    },
+    clock::{Clock, SystemClock},
+    codec::{BincodeCodec, Codec},
+    consistency::check_transfer_consistency,
+    events::{merge_event_logs, sort_key, DebitStage, EventKey, VersionedEvent, EVENT_VERSION},
+    genesis::{assemble_genesis, get_genesis},
+    money::{money_from_u128, plan_split_transfer, sum_credit_amounts},
+    multisig::{MultiSigCredit, MultiSigDebit},
+    payload::{actor_payloads, PayloadFormat, LEGACY_WIRE_VERSION},
+    proofs::{
+        build_transfer_proof, combine_credit_proofs, credit_proof_from_transfer, group_key_id,
+        proof_fingerprint, proof_size_bytes, transfer_proof_size_bytes, ProofFingerprint,
+    },
+    read_only_replica::ReadOnlyReplica,
+    replica::Replica as TransferReplica,
+    replica_signing::ReplicaSigning,
+    verify::{
+        credit_proof_from_canonical_bytes, signature_from_canonical_bytes,
+        transfer_proof_from_canonical_bytes, verify_credit_proof, verify_propagated_with_keys,
+        verify_transfer_proof,
+    },
+    wallet::{Wallet, WalletSnapshot},
+    wallet_replica::{
+        BurnPolicy, CreditClassification, PendingRecord, ReplicaDiagnostics, VerifyContext,
+        VerifyFailureHook, VerifyKind, WalletReplica,
+    },
 };
+#[cfg(feature = "testing")]
+pub use self::{payload::sign_transfer_as_actor, replay::ReplayHarness};
 
 use serde::{Deserialize, Serialize};
 use sn_data_types::{
@@ -44,12 +91,19 @@ use sn_data_types::{
 };
 use std::collections::HashSet;
 
+/// The index of a Replica key share, in the group set.
+/// A distinct type from other numbers passed next to it, so that they can't be mixed up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KeyIndex(pub usize);
+
 type Outcome<T> = Result<Option<T>>;
 
 trait TernaryResult<T> {
     fn success(item: T) -> Self;
     fn no_change() -> Self;
     fn rejected(error: Error) -> Self;
+    /// The error of a rejected outcome, without consuming it, f.ex. for logging.
+    fn rejection_reason(&self) -> Option<&Error>;
 }
 
 impl<T> TernaryResult<T> for Outcome<T> {
@@ -62,6 +116,17 @@ impl<T> TernaryResult<T> for Outcome<T> {
     fn rejected(error: Error) -> Self {
         Err(error)
     }
+    fn rejection_reason(&self) -> Option<&Error> {
+        self.as_ref().err()
+    }
+}
+
+/// Serialises `value` into the provided buffer, replacing its contents.
+/// Reusing the same buffer across calls avoids a fresh allocation per signing.
+/// The written bytes are identical to those of `bincode::serialize`.
+pub fn serialize_into<T: Serialize + ?Sized>(buf: &mut Vec<u8>, value: &T) -> Result<()> {
+    buf.clear();
+    bincode::serialize_into(&mut *buf, value).map_err(|e| Error::NetworkOther(e.to_string()))
 }
 
 /// A received credit, contains the CreditAgreementProof from the sender Replicas,
@@ -177,8 +242,8 @@ pub struct TransferRegistrationSent {
 #[allow(unused)]
 mod test {
     use crate::{
-        actor::Actor, genesis, replica::Replica, ActorEvent, Error, ReplicaEvent, ReplicaValidator,
-        TransferInitiated, Wallet,
+        actor::Actor, genesis, replica::Replica, serialize_into, ActorEvent, Error, Outcome,
+        ReplicaEvent, ReplicaValidator, TernaryResult, TransferInitiated, Wallet,
     };
     use crdts::{
         quickcheck::{quickcheck, TestResult},
@@ -200,6 +265,27 @@ mod test {
         }}
     }
 
+    // ------------------------------------------------------------------------
+    // ------------------------ Outcome ---------------------------------------
+    // ------------------------------------------------------------------------
+
+    #[test]
+    fn peeks_at_rejection_reason() {
+        let rejected: Outcome<()> = Outcome::rejected(Error::InvalidOperation);
+        assert!(matches!(
+            rejected.rejection_reason(),
+            Some(Error::InvalidOperation)
+        ));
+        // The outcome is still there.
+        assert!(matches!(rejected, Err(Error::InvalidOperation)));
+
+        let success: Outcome<()> = Outcome::success(());
+        assert!(success.rejection_reason().is_none());
+
+        let no_change: Outcome<()> = Outcome::no_change();
+        assert!(no_change.rejection_reason().is_none());
+    }
+
     // ------------------------------------------------------------------------
     // ------------------------ Basic Transfer --------------------------------
     // ------------------------------------------------------------------------
@@ -257,6 +343,22 @@ mod test {
         Ok(())
     }
 
+    // ------------------------------------------------------------------------
+    // ------------------------ Serialisation ---------------------------------
+    // ------------------------------------------------------------------------
+
+    #[test]
+    fn serialize_into_matches_bincode() -> Result<()> {
+        let credit_proof = get_genesis()?;
+        // A dirty buffer, as when reused from a previous signing.
+        let mut buf = vec![1, 2, 3];
+        serialize_into(&mut buf, &credit_proof)?;
+        let expected =
+            bincode::serialize(&credit_proof).map_err(|e| Error::NetworkOther(e.to_string()))?;
+        assert_eq!(buf, expected);
+        Ok(())
+    }
+
     // ------------------------------------------------------------------------
     // ------------------------ Basic Transfer Body ---------------------------
     // ------------------------------------------------------------------------
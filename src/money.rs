@@ -0,0 +1,161 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use sn_data_types::{CreditAgreementProof, Error, Money, Result};
+
+/// Sums the amounts of a batch of credits, such as one received at sync,
+/// so that the total can be reconciled against an expected figure.
+/// Returns `Error::ExcessiveValue` if the sum overflows.
+pub fn sum_credit_amounts(proofs: &[CreditAgreementProof]) -> Result<Money> {
+    proofs.iter().try_fold(Money::zero(), |sum, proof| {
+        sum.checked_add(proof.signed_credit.credit.amount)
+            .ok_or(Error::ExcessiveValue)
+    })
+}
+
+/// Converts an amount of nanos, f.ex. a sum computed in u128
+/// to avoid intermediate overflow, back to money.
+/// Returns `Error::ExcessiveValue` if it doesn't fit in a u64.
+pub fn money_from_u128(nanos: u128) -> Result<Money> {
+    if nanos > u64::MAX as u128 {
+        return Err(Error::ExcessiveValue);
+    }
+    Ok(Money::from_nano(nanos as u64))
+}
+
+/// Plans the split of a transfer too large for a single debit,
+/// into a chain of (counter, amount) debits starting at `start_counter`.
+/// Each debit carries `max_per_debit`, except for the final one, which carries the remainder.
+pub fn plan_split_transfer(
+    total: Money,
+    max_per_debit: Money,
+    start_counter: u64,
+) -> Result<Vec<(u64, Money)>> {
+    let max = max_per_debit.as_nano();
+    if max == 0 {
+        return Err(Error::from("Max amount per debit must be more than zero."));
+    }
+    let mut plan = vec![];
+    let mut remaining = total.as_nano();
+    let mut counter = start_counter;
+    while remaining > 0 {
+        let amount = remaining.min(max);
+        plan.push((counter, Money::from_nano(amount)));
+        remaining -= amount;
+        counter += 1;
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sn_data_types::{Credit, Keypair, PublicKey, SignedCredit};
+
+    #[test]
+    fn sums_credit_amounts() -> Result<()> {
+        let proofs = vec![
+            get_credit_proof(10),
+            get_credit_proof(20),
+            get_credit_proof(30),
+        ];
+
+        assert_eq!(sum_credit_amounts(&proofs)?, Money::from_nano(60));
+        assert_eq!(sum_credit_amounts(&[])?, Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn detects_overflowing_sum() {
+        let proofs = vec![get_credit_proof(u64::MAX), get_credit_proof(1)];
+
+        let result = sum_credit_amounts(&proofs);
+
+        assert!(matches!(result, Err(Error::ExcessiveValue)));
+    }
+
+    #[test]
+    fn converts_u128_in_range() -> Result<()> {
+        assert_eq!(money_from_u128(0)?, Money::zero());
+        assert_eq!(
+            money_from_u128(u64::MAX as u128)?,
+            Money::from_nano(u64::MAX)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_u128_out_of_range() {
+        let result = money_from_u128(u64::MAX as u128 + 1);
+
+        assert!(matches!(result, Err(Error::ExcessiveValue)));
+    }
+
+    #[test]
+    fn splits_transfer_evenly() -> Result<()> {
+        let plan = plan_split_transfer(Money::from_nano(30), Money::from_nano(10), 5)?;
+
+        assert_eq!(
+            plan,
+            vec![
+                (5, Money::from_nano(10)),
+                (6, Money::from_nano(10)),
+                (7, Money::from_nano(10))
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn splits_transfer_with_remainder() -> Result<()> {
+        let plan = plan_split_transfer(Money::from_nano(25), Money::from_nano(10), 0)?;
+
+        assert_eq!(
+            plan,
+            vec![
+                (0, Money::from_nano(10)),
+                (1, Money::from_nano(10)),
+                (2, Money::from_nano(5))
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cannot_split_by_zero() {
+        let result = plan_split_transfer(Money::from_nano(25), Money::zero(), 0);
+
+        assert!(result.is_err());
+    }
+
+    fn get_credit_proof(amount: u64) -> CreditAgreementProof {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let credit = Credit {
+            id: Default::default(),
+            recipient: get_random_pk(),
+            amount: Money::from_nano(amount),
+            msg: "credit".to_string(),
+        };
+        CreditAgreementProof {
+            signed_credit: SignedCredit {
+                credit,
+                actor_signature: keypair.sign(b"credit"),
+            },
+            debiting_replicas_sig: keypair.sign(b"credit"),
+            debiting_replicas_keys: threshold_crypto::SecretKeySet::random(
+                0,
+                &mut rand::thread_rng(),
+            )
+            .public_keys(),
+        }
+    }
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(threshold_crypto::SecretKey::random().public_key())
+    }
+}
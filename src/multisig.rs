@@ -0,0 +1,57 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use serde::{Deserialize, Serialize};
+use sn_data_types::{Credit, Debit, Error, Result, SignatureShare};
+use std::collections::BTreeSet;
+use threshold_crypto::PublicKeySet;
+
+/// A debit of a wallet controlled by a group of actors,
+/// carrying the signature shares of the actors that agreed to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiSigDebit {
+    /// The debit.
+    pub debit: Debit,
+    /// The signature shares of the actors, over the serialised debit.
+    pub actor_signatures: Vec<SignatureShare>,
+}
+
+/// The credit corresponding to a `MultiSigDebit`,
+/// carrying the signature shares of the actors that agreed to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultiSigCredit {
+    /// The credit.
+    pub credit: Credit,
+    /// The signature shares of the actors, over the serialised credit.
+    pub actor_signatures: Vec<SignatureShare>,
+}
+
+/// Verifies that at least `threshold` distinct actors of the set
+/// have validly signed the value.
+pub(crate) fn verify_actor_shares<T: Serialize>(
+    value: &T,
+    shares: &[SignatureShare],
+    actors: &PublicKeySet,
+    threshold: usize,
+) -> Result<()> {
+    let bytes = match bincode::serialize(value) {
+        Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
+        Ok(bytes) => bytes,
+    };
+    // A share counts once, however many times it is repeated.
+    let signers: BTreeSet<_> = shares
+        .iter()
+        .filter(|s| actors.public_key_share(s.index).verify(&s.share, &bytes))
+        .map(|s| s.index)
+        .collect();
+    if signers.len() >= threshold {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
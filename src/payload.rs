@@ -0,0 +1,181 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::Codec;
+use serde::Serialize;
+use sn_data_types::{Credit, Debit, Error, PublicKey, Result, Signature, SignedTransfer};
+#[cfg(feature = "testing")]
+use sn_data_types::{Keypair, SignedCredit, SignedDebit};
+
+/// The wire version of the legacy format, where Replicas sign
+/// the plain serialised value. Later versions prefix the version byte.
+pub const LEGACY_WIRE_VERSION: u8 = 1;
+
+/// The format of the bytes that Replicas sign,
+/// which the signing and the verifying Replicas must agree on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadFormat {
+    /// The version of the wire format.
+    pub wire_version: u8,
+    /// The network that signatures are bound to, so that a proof
+    /// can't be replayed on a forked network sharing the history.
+    /// None binds signatures to no network.
+    pub network_id: Option<[u8; 32]>,
+    /// The transfer system that signatures are bound to, f.ex. "payments" or "rewards",
+    /// so that systems sharing the keys can't verify each other's signatures.
+    /// Empty binds signatures to no context.
+    pub context: Vec<u8>,
+}
+
+impl Default for PayloadFormat {
+    /// The legacy format, bound to no network nor context.
+    fn default() -> Self {
+        Self {
+            wire_version: LEGACY_WIRE_VERSION,
+            network_id: None,
+            context: vec![],
+        }
+    }
+}
+
+/// The bytes that the actor must sign for the debit and the credit
+/// of the transfer, i.e. the ones that Replicas verify the actor signatures over.
+pub fn actor_payloads(transfer: &SignedTransfer) -> Result<(Vec<u8>, Vec<u8>)> {
+    Ok((
+        actor_debit_payload(&transfer.debit.debit)?,
+        actor_credit_payload(&transfer.credit.credit)?,
+    ))
+}
+
+/// Signs the debit and the credit as the actor with the given keypair,
+/// over the same bytes that Replicas verify the actor signature over.
+#[cfg(feature = "testing")]
+pub fn sign_transfer_as_actor(
+    debit: Debit,
+    credit: Credit,
+    keypair: &Keypair,
+) -> Result<(SignedDebit, SignedCredit)> {
+    let signed_debit = SignedDebit {
+        actor_signature: keypair.sign(&actor_debit_payload(&debit)?),
+        debit,
+    };
+    let signed_credit = SignedCredit {
+        actor_signature: keypair.sign(&actor_credit_payload(&credit)?),
+        credit,
+    };
+    Ok((signed_debit, signed_credit))
+}
+
+/// The bytes that the actor signs for the debit.
+pub(crate) fn actor_debit_payload(debit: &Debit) -> Result<Vec<u8>> {
+    bincode::serialize(debit).map_err(|_| Error::NetworkOther("Could not serialise debit".into()))
+}
+
+/// The bytes that the actor signs for the credit.
+pub(crate) fn actor_credit_payload(credit: &Credit) -> Result<Vec<u8>> {
+    bincode::serialize(credit).map_err(|_| Error::NetworkOther("Could not serialise credit".into()))
+}
+
+/// Writes the bytes that Replicas sign for the value under
+/// the given format into the buffer, replacing its contents.
+/// The serialised value is prefixed with the length and bytes of the context, if any,
+/// then the network id, if any, and then the wire version, unless it is the legacy one.
+pub(crate) fn signing_payload_into<C: Codec, T: Serialize + ?Sized>(
+    codec: &C,
+    buf: &mut Vec<u8>,
+    format: &PayloadFormat,
+    value: &T,
+) -> Result<()> {
+    codec.serialize_into(buf, value)?;
+    let mut prefix = Vec::new();
+    if !format.context.is_empty() {
+        prefix.extend_from_slice(&(format.context.len() as u32).to_le_bytes());
+        prefix.extend_from_slice(&format.context);
+    }
+    if let Some(network_id) = &format.network_id {
+        prefix.extend_from_slice(network_id);
+    }
+    if format.wire_version != LEGACY_WIRE_VERSION {
+        prefix.push(format.wire_version);
+    }
+    if !prefix.is_empty() {
+        let _ = buf.splice(0..0, prefix);
+    }
+    Ok(())
+}
+
+/// The bytes that Replicas sign for the value under the given format.
+pub(crate) fn signing_payload<C: Codec, T: Serialize + ?Sized>(
+    codec: &C,
+    format: &PayloadFormat,
+    value: &T,
+) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    signing_payload_into(codec, &mut buf, format, value)?;
+    Ok(buf)
+}
+
+/// When a signature over the value does not verify under our format,
+/// this tells whether it was instead made under another wire version, or without
+/// our network id, so that a mixed-version cluster gets a clear error instead of
+/// a bare invalid signature.
+/// The versions tried are those from the legacy one up to one past ours, as replicas
+/// in a rolling upgrade are at most a version apart. The id of another network can't
+/// be guessed, so only a signature bound to no network is told apart.
+pub(crate) fn format_mismatch<C: Codec, T: Serialize + ?Sized>(
+    codec: &C,
+    keys: &[PublicKey],
+    signature: &Signature,
+    value: &T,
+    format: &PayloadFormat,
+) -> Option<Error> {
+    let last_version = format
+        .wire_version
+        .saturating_add(1)
+        .max(LEGACY_WIRE_VERSION);
+    let mut network_ids = vec![format.network_id];
+    if format.network_id.is_some() {
+        network_ids.push(None);
+    }
+    for network_id in network_ids {
+        for wire_version in LEGACY_WIRE_VERSION..=last_version {
+            let signed = PayloadFormat {
+                wire_version,
+                network_id,
+                context: format.context.clone(),
+            };
+            if &signed == format {
+                continue;
+            }
+            let bytes = signing_payload(codec, &signed, value).ok()?;
+            if keys.iter().any(|key| key.verify(signature, &bytes).is_ok()) {
+                return Some(mismatch_error(&signed, format));
+            }
+        }
+    }
+    None
+}
+
+/// The error of a signature made under the `signed` format, instead of ours.
+fn mismatch_error(signed: &PayloadFormat, ours: &PayloadFormat) -> Error {
+    let msg = if signed.network_id == ours.network_id {
+        format!(
+            "Wire version mismatch: signed under version {}, but this replica uses version {}.",
+            signed.wire_version, ours.wire_version
+        )
+    } else if signed.wire_version == ours.wire_version {
+        "Network mismatch: signed under no network, but this replica is bound to one.".to_string()
+    } else {
+        format!(
+            "Wire format mismatch: signed under version {} and no network, \
+            but this replica uses version {} and is bound to a network.",
+            signed.wire_version, ours.wire_version
+        )
+    };
+    Error::NetworkOther(msg)
+}
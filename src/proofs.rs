@@ -0,0 +1,356 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    payload::{signing_payload, PayloadFormat},
+    Codec,
+};
+use serde::Serialize;
+use sn_data_types::{
+    CreditAgreementProof, CreditId, Error, Money, PublicKey, Result, Signature, SignatureShare,
+    SignedCredit, SignedDebit, TransferAgreementProof,
+};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use threshold_crypto::PublicKeySet;
+use xor_name::XorName;
+
+/// The verifiable core of a credit proof, for recording
+/// accepted proofs in audit logs without storing them in full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ProofFingerprint {
+    /// The id of the credit.
+    pub credit_id: CreditId,
+    /// The amount of the credit.
+    pub amount: Money,
+    /// The recipient of the credit.
+    pub recipient: PublicKey,
+    /// The key of the group of Replicas that signed the proof.
+    pub signer: threshold_crypto::PublicKey,
+}
+
+impl Display for ProofFingerprint {
+    /// The hex encoded digest of the fingerprint.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let bytes = bincode::serialize(self).map_err(|_| fmt::Error)?;
+        for byte in XorName::from_content(&bytes).0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// The fingerprint of the credit proof, see `ProofFingerprint`.
+pub fn proof_fingerprint(proof: &CreditAgreementProof) -> ProofFingerprint {
+    ProofFingerprint {
+        credit_id: *proof.id(),
+        amount: proof.amount(),
+        recipient: proof.signed_credit.recipient(),
+        signer: proof.debiting_replicas_keys.public_key(),
+    }
+}
+
+/// A short and stable id of the group of the key set, i.e. the digest
+/// of its public key, f.ex. for referring to the group in logs.
+pub fn group_key_id(set: &PublicKeySet) -> [u8; 32] {
+    XorName::from_content(&set.public_key().to_bytes()).0
+}
+
+/// Assembles the proof of an agreed transfer from the separately collected
+/// debit and credit signature shares of the Replicas, signed under the given codec and format.
+/// Each share set must reach quorum (threshold + 1) of the peer replicas.
+pub fn build_transfer_proof<C: Codec>(
+    codec: &C,
+    signed_debit: SignedDebit,
+    signed_credit: SignedCredit,
+    debit_shares: BTreeMap<usize, SignatureShare>,
+    credit_shares: BTreeMap<usize, SignatureShare>,
+    peer_replicas: &PublicKeySet,
+    format: &PayloadFormat,
+) -> Result<TransferAgreementProof> {
+    let debit_bytes = signing_payload(codec, format, &signed_debit)?;
+    let credit_bytes = signing_payload(codec, format, &signed_credit)?;
+    let debit_sig = combine_shares(
+        peer_replicas,
+        &to_threshold_shares(debit_shares),
+        &debit_bytes,
+    )?;
+    let credit_sig = combine_shares(
+        peer_replicas,
+        &to_threshold_shares(credit_shares),
+        &credit_bytes,
+    )?;
+    Ok(TransferAgreementProof {
+        signed_debit,
+        debit_sig: Signature::Bls(debit_sig),
+        signed_credit,
+        credit_sig: Signature::Bls(credit_sig),
+        debiting_replicas_keys: peer_replicas.clone(),
+    })
+}
+
+/// The proof for propagating the credit of the agreed transfer,
+/// with the given signature of the debiting Replicas over the signed credit.
+pub fn credit_proof_from_transfer(
+    proof: &TransferAgreementProof,
+    debiting_replicas_sig: Signature,
+    debiting_replicas_keys: PublicKeySet,
+) -> CreditAgreementProof {
+    CreditAgreementProof {
+        signed_credit: proof.signed_credit.clone(),
+        debiting_replicas_sig,
+        debiting_replicas_keys,
+    }
+}
+
+/// Combines the credit proofs built by a quorum of the Replicas, each carrying
+/// the signature share of one of them, into the proof signed by the group,
+/// see `ReplicaSigning::build_credit_proof`. The shares must be signed under the given codec and format.
+pub fn combine_credit_proofs<C: Codec>(
+    codec: &C,
+    proofs: &[CreditAgreementProof],
+    peer_replicas: &PublicKeySet,
+    format: &PayloadFormat,
+) -> Result<CreditAgreementProof> {
+    check_quorum(proofs.len(), peer_replicas)?;
+    let signed_credit = &proofs[0].signed_credit;
+    let mut shares = BTreeMap::new();
+    for proof in proofs {
+        if &proof.signed_credit != signed_credit {
+            return Err(Error::NetworkOther(
+                "Could not combine credit proofs of different credits.".into(),
+            ));
+        }
+        match &proof.debiting_replicas_sig {
+            Signature::BlsShare(share) => {
+                let _ = shares.insert(share.index, share.share.clone());
+            }
+            _ => return Err(Error::InvalidSignature),
+        }
+    }
+    let credit_bytes = signing_payload(codec, format, signed_credit)?;
+    let signature = combine_shares(peer_replicas, &shares, &credit_bytes)?;
+    Ok(CreditAgreementProof {
+        signed_credit: signed_credit.clone(),
+        debiting_replicas_sig: Signature::Bls(signature),
+        debiting_replicas_keys: peer_replicas.clone(),
+    })
+}
+
+/// Combines the shares into the signature of the key set.
+/// Every share is first verified against its index in the key set,
+/// since shares from a foreign set would combine into a garbage signature.
+/// A foreign share is reported as `Error::Unexpected`, naming its index.
+pub(crate) fn combine_shares(
+    peer_replicas: &PublicKeySet,
+    shares: &BTreeMap<usize, threshold_crypto::SignatureShare>,
+    data: &[u8],
+) -> Result<threshold_crypto::Signature> {
+    check_quorum(shares.len(), peer_replicas)?;
+    for (index, share) in shares {
+        if !peer_replicas.public_key_share(*index).verify(share, data) {
+            return Err(Error::Unexpected(format!(
+                "Could not aggregate: share {} is not from the given key set.",
+                index
+            )));
+        }
+    }
+    peer_replicas
+        .combine_signatures(shares)
+        .map_err(|e| Error::Unexpected(e.to_string()))
+}
+
+/// Checks that there are enough shares to combine, i.e. a quorum (threshold + 1)
+/// of the key set, so that a short count is reported as such.
+pub(crate) fn check_quorum(shares_len: usize, pk_set: &PublicKeySet) -> Result<()> {
+    let quorum = pk_set.threshold() + 1;
+    if shares_len < quorum {
+        return Err(Error::NetworkOther(format!(
+            "have {} shares, need {}",
+            shares_len, quorum
+        )));
+    }
+    Ok(())
+}
+
+/// The serialised size of the credit proof, computed without serialising it,
+/// f.ex. for rejecting oversized proofs before handling them.
+pub fn proof_size_bytes(proof: &CreditAgreementProof) -> Result<usize> {
+    serialized_size(proof)
+}
+
+/// The serialised size of the transfer proof, computed without serialising it.
+pub fn transfer_proof_size_bytes(proof: &TransferAgreementProof) -> Result<usize> {
+    serialized_size(proof)
+}
+
+fn serialized_size<T: serde::Serialize>(value: &T) -> Result<usize> {
+    bincode::serialized_size(value)
+        .map(|size| size as usize)
+        .map_err(|e| Error::NetworkOther(e.to_string()))
+}
+
+pub(crate) fn to_threshold_shares(
+    shares: BTreeMap<usize, SignatureShare>,
+) -> BTreeMap<usize, threshold_crypto::SignatureShare> {
+    shares
+        .into_iter()
+        .map(|(index, share)| (index, share.share))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sn_data_types::Credit;
+    use threshold_crypto::{SecretKey, SecretKeySet};
+
+    #[test]
+    fn combines_shares_from_the_key_set() -> Result<()> {
+        let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let data = b"data";
+        let shares = get_shares(&sk_set, 0..2, data);
+
+        let signature = combine_shares(&sk_set.public_keys(), &shares, data)?;
+
+        assert!(sk_set.public_keys().public_key().verify(&signature, data));
+        Ok(())
+    }
+
+    #[test]
+    fn cannot_combine_foreign_shares() {
+        let sk_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let foreign_set = SecretKeySet::random(1, &mut rand::thread_rng());
+        let data = b"data";
+        let mut shares = get_shares(&sk_set, 0..2, data);
+        let _ = shares.insert(2, foreign_set.secret_key_share(2).sign(data));
+
+        let result = combine_shares(&sk_set.public_keys(), &shares, data);
+
+        match result {
+            Err(Error::Unexpected(msg)) => assert_eq!(
+                msg,
+                "Could not aggregate: share 2 is not from the given key set."
+            ),
+            _ => panic!("Expected a foreign share, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn rejects_exactly_threshold_shares() {
+        let sk_set = SecretKeySet::random(2, &mut rand::thread_rng());
+        let data = b"data";
+        let shares = get_shares(&sk_set, 0..2, data);
+
+        let result = combine_shares(&sk_set.public_keys(), &shares, data);
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(msg, "have 2 shares, need 3"),
+            _ => panic!("Expected a missing quorum, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn accepts_threshold_plus_one_shares() {
+        let pk_set = SecretKeySet::random(2, &mut rand::thread_rng()).public_keys();
+        assert!(check_quorum(3, &pk_set).is_ok());
+    }
+
+    #[test]
+    fn fingerprints_are_deterministic_and_distinct() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set, 10)?;
+        let copy: CreditAgreementProof = bincode::deserialize(
+            &bincode::serialize(&proof).map_err(|e| Error::from(e.to_string()))?,
+        )
+        .map_err(|e| Error::from(e.to_string()))?;
+        let other = get_credit_proof(&sk_set, 10)?;
+
+        assert_eq!(proof_fingerprint(&proof), proof_fingerprint(&copy));
+        assert_eq!(
+            proof_fingerprint(&proof).to_string(),
+            proof_fingerprint(&copy).to_string()
+        );
+        assert_eq!(proof_fingerprint(&proof).to_string().len(), 64);
+        assert_ne!(proof_fingerprint(&proof), proof_fingerprint(&other));
+        assert_ne!(
+            proof_fingerprint(&proof).to_string(),
+            proof_fingerprint(&other).to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn group_key_ids_are_deterministic_and_distinct() {
+        let set = SecretKeySet::random(1, &mut rand::thread_rng()).public_keys();
+        let other = SecretKeySet::random(1, &mut rand::thread_rng()).public_keys();
+
+        assert_eq!(group_key_id(&set), group_key_id(&set.clone()));
+        assert_ne!(group_key_id(&set), group_key_id(&other));
+    }
+
+    #[test]
+    fn carries_over_signed_credit_of_transfer() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let credit_proof = get_credit_proof(&sk_set, 10)?;
+        let signature = credit_proof.debiting_replicas_sig.clone();
+        let transfer_proof = TransferAgreementProof {
+            signed_debit: SignedDebit {
+                debit: sn_data_types::Debit {
+                    id: crdts::Dot::new(credit_proof.recipient(), 0),
+                    amount: credit_proof.amount(),
+                },
+                actor_signature: signature.clone(),
+            },
+            debit_sig: signature.clone(),
+            signed_credit: credit_proof.signed_credit.clone(),
+            credit_sig: signature.clone(),
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+
+        let derived = credit_proof_from_transfer(&transfer_proof, signature, sk_set.public_keys());
+
+        assert_eq!(derived.signed_credit, transfer_proof.signed_credit);
+        assert_eq!(derived, credit_proof);
+        Ok(())
+    }
+
+    fn get_credit_proof(sk_set: &SecretKeySet, amount: u64) -> Result<CreditAgreementProof> {
+        let credit = Credit {
+            id: XorName::random(),
+            recipient: PublicKey::from(SecretKey::random().public_key()),
+            amount: Money::from_nano(amount),
+            msg: "asdf".to_string(),
+        };
+        let credit_bytes = bincode::serialize(&credit).map_err(|e| Error::from(e.to_string()))?;
+        let shares = get_shares(sk_set, 0..1, &credit_bytes);
+        let signature = Signature::Bls(combine_shares(
+            &sk_set.public_keys(),
+            &shares,
+            &credit_bytes,
+        )?);
+        Ok(CreditAgreementProof {
+            signed_credit: SignedCredit {
+                credit,
+                actor_signature: signature.clone(),
+            },
+            debiting_replicas_sig: signature,
+            debiting_replicas_keys: sk_set.public_keys(),
+        })
+    }
+
+    fn get_shares(
+        sk_set: &SecretKeySet,
+        indices: std::ops::Range<usize>,
+        data: &[u8],
+    ) -> BTreeMap<usize, threshold_crypto::SignatureShare> {
+        indices
+            .map(|i| (i, sk_set.secret_key_share(i).sign(data)))
+            .collect()
+    }
+}
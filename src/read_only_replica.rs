@@ -0,0 +1,90 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{wallet::WalletSnapshot, wallet_replica::WalletReplica, Outcome};
+use sn_data_types::{CreditAgreementProof, Money, PublicKey, Result, SignedCredit, SignedDebit};
+
+/// A view of a Replica for observer nodes, which validate
+/// and track balances, but must never sign or mutate.
+/// Only the queries and validations of the Replica are exposed:
+///
+/// ```compile_fail
+/// fn mutate(replica: &mut sn_transfers::ReadOnlyReplica, event: sn_data_types::ReplicaEvent) {
+///     replica.apply(event).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadOnlyReplica {
+    replica: WalletReplica,
+}
+
+impl From<WalletReplica> for ReadOnlyReplica {
+    fn from(replica: WalletReplica) -> Self {
+        Self { replica }
+    }
+}
+
+impl ReadOnlyReplica {
+    ///
+    pub fn balance(&self) -> Money {
+        self.replica.balance()
+    }
+
+    ///
+    pub fn wallet(&self) -> Option<WalletSnapshot> {
+        self.replica.wallet()
+    }
+
+    /// Validation of a debit, see `WalletReplica::validate`.
+    pub fn validate(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+    ) -> Outcome<()> {
+        self.replica.validate(signed_debit, signed_credit)
+    }
+
+    /// Validation of a propagated credit, see `WalletReplica::receive_propagated`.
+    pub fn receive_propagated<F: FnOnce() -> Result<PublicKey>>(
+        &self,
+        credit_proof: &CreditAgreementProof,
+        past_key: F,
+    ) -> Outcome<()> {
+        self.replica.receive_propagated(credit_proof, past_key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::KeyIndex;
+    use threshold_crypto::{SecretKey, SecretKeySet};
+
+    #[test]
+    fn exposes_queries_of_the_replica() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let peer_replicas = sk_set.public_keys();
+        let id = PublicKey::from(SecretKey::random().public_key());
+        let replica = WalletReplica::from_history(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            vec![],
+        )?;
+
+        let view = ReadOnlyReplica::from(replica.clone());
+
+        assert_eq!(view.balance(), replica.balance());
+        assert_eq!(
+            view.wallet().map(|w| w.debit_version),
+            replica.wallet().map(|w| w.debit_version)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    build_transfer_proof, credit_proof_from_transfer, proofs::combine_shares, BincodeCodec,
+    KeyIndex, ReplicaSigning, WalletReplica,
+};
+use crdts::Dot;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sn_data_types::{
+    Credit, CreditAgreementProof, Debit, Error, Keypair, Money, PublicKey, ReplicaEvent, Result,
+    Signature, SignedCredit, SignedDebit, SignedTransfer, TransferAgreementProof,
+    TransferPropagated, TransferRegistered,
+};
+use std::collections::BTreeMap;
+use threshold_crypto::SecretKeySet;
+
+/// The max amount of a generated credit.
+const MAX_CREDIT: u64 = 1_000_000;
+
+/// Replays a random, but valid, sequence of events against a `WalletReplica`,
+/// checking the invariants of the Replica after each event.
+/// The sequence is determined by the seed, so a failing one can be reproduced.
+/// Every event passes the cmd of the Replica before it is applied,
+/// i.e. genesis, propagated credits, and validated then registered debits in order.
+#[derive(Debug)]
+pub struct ReplayHarness {
+    rng: StdRng,
+    keypair: Keypair,
+    sk_set: SecretKeySet,
+    signing: ReplicaSigning,
+    replica: WalletReplica,
+    events: Vec<ReplicaEvent>,
+}
+
+impl ReplayHarness {
+    /// A new harness, with an empty Replica, for the sequence of the seed.
+    pub fn new(seed: u64) -> Result<Self> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let keypair = Keypair::new_ed25519(&mut rng);
+        let sk_set = SecretKeySet::random(0, &mut rng);
+        let peer_replicas = sk_set.public_keys();
+        let signing = ReplicaSigning::new(
+            sk_set.secret_key_share(0),
+            KeyIndex(0),
+            peer_replicas.clone(),
+        );
+        let replica = WalletReplica::from_history(
+            keypair.public_key(),
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            vec![],
+        )?;
+        Ok(Self {
+            rng,
+            keypair,
+            sk_set,
+            signing,
+            replica,
+            events: vec![],
+        })
+    }
+
+    /// The Replica that the events were applied to.
+    pub fn replica(&self) -> &WalletReplica {
+        &self.replica
+    }
+
+    /// The events applied so far, in order.
+    pub fn events(&self) -> &[ReplicaEvent] {
+        &self.events
+    }
+
+    /// Generates and applies at least the given number of events,
+    /// starting with genesis if not yet applied.
+    /// Returns the first cmd rejection or broken invariant.
+    pub fn run(&mut self, count: usize) -> Result<()> {
+        let target = self.events.len() + count;
+        if self.events.is_empty() {
+            self.genesis()?;
+        }
+        while self.events.len() < target {
+            let balance = self.replica.balance().as_nano();
+            if balance > 0 && self.rng.gen_bool(0.5) {
+                let amount = self.rng.gen_range(1, balance + 1);
+                self.debit(amount)?;
+            } else {
+                let amount = self.rng.gen_range(1, MAX_CREDIT + 1);
+                self.credit(amount)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn genesis(&mut self) -> Result<()> {
+        let amount = self.rng.gen_range(1, MAX_CREDIT + 1);
+        let credit = Credit {
+            id: Default::default(),
+            recipient: self.keypair.public_key(),
+            amount: Money::from_nano(amount),
+            msg: "genesis".to_string(),
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: self.group_sign(&credit)?,
+            credit,
+        };
+        let credit_proof = CreditAgreementProof {
+            debiting_replicas_sig: self.group_sign(&signed_credit)?,
+            signed_credit,
+            debiting_replicas_keys: self.sk_set.public_keys(),
+        };
+        let _ = self
+            .replica
+            .genesis(&credit_proof, || Err(Error::NoSuchSender))?
+            .ok_or_else(|| Error::from("Genesis was not applied"))?;
+        self.propagate(credit_proof)
+    }
+
+    /// Receives a credit from a random sender.
+    fn credit(&mut self, amount: u64) -> Result<()> {
+        let sender = Keypair::new_ed25519(&mut self.rng);
+        let transfer = get_signed_transfer(&sender, 0, amount, self.keypair.public_key())?;
+        let transfer_proof = self.agree(&transfer)?;
+        let credit_proof = credit_proof_from_transfer(
+            &transfer_proof,
+            transfer_proof.credit_sig.clone(),
+            transfer_proof.debiting_replicas_keys.clone(),
+        );
+        let _ = self
+            .replica
+            .receive_propagated(&credit_proof, || Err(Error::NoSuchSender))?
+            .ok_or_else(|| Error::from("Credit was already received"))?;
+        self.propagate(credit_proof)
+    }
+
+    /// Validates and registers a debit at the next counter.
+    fn debit(&mut self, amount: u64) -> Result<()> {
+        let counter = self.replica.next_expected_counter();
+        let secret_key: threshold_crypto::SecretKey = self.rng.gen();
+        let recipient = PublicKey::from(secret_key.public_key());
+        let transfer = get_signed_transfer(&self.keypair, counter, amount, recipient)?;
+        let _ = self
+            .replica
+            .validate(&transfer.debit, &transfer.credit)?
+            .ok_or_else(|| Error::from("Debit was not validated"))?;
+        let validated = self
+            .signing
+            .sign_transfer_validated(&transfer)?
+            .ok_or_else(|| Error::from("Debit was not signed"))?;
+        self.apply(ReplicaEvent::TransferValidated(validated))?;
+
+        let transfer_proof = self.agree(&transfer)?;
+        let registered: TransferRegistered = self
+            .replica
+            .register(&transfer_proof, || Err(Error::NoSuchSender))?
+            .ok_or_else(|| Error::from("Debit was not registered"))?;
+        self.apply(ReplicaEvent::TransferRegistered(registered))
+    }
+
+    fn propagate(&mut self, credit_proof: CreditAgreementProof) -> Result<()> {
+        let crediting_replica_sig = self
+            .signing
+            .sign_credit_proof(&credit_proof)?
+            .ok_or_else(|| Error::from("Credit proof was not signed"))?;
+        self.apply(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof,
+            crediting_replica_sig,
+            crediting_replica_keys: PublicKey::Bls(self.signing.replicas_pk_set().public_key()),
+        }))
+    }
+
+    fn apply(&mut self, event: ReplicaEvent) -> Result<()> {
+        self.replica.apply(event.clone())?;
+        self.events.push(event);
+        self.replica.check_state_invariants()
+    }
+
+    /// The proof of our group agreeing on the transfer.
+    fn agree(&self, transfer: &SignedTransfer) -> Result<TransferAgreementProof> {
+        let (debit_share, credit_share) = self
+            .signing
+            .sign_transfer(transfer)?
+            .ok_or_else(|| Error::from("Transfer was not signed"))?;
+        let debit_shares: BTreeMap<_, _> =
+            vec![(debit_share.index, debit_share)].into_iter().collect();
+        let credit_shares: BTreeMap<_, _> = vec![(credit_share.index, credit_share)]
+            .into_iter()
+            .collect();
+        build_transfer_proof(
+            &BincodeCodec,
+            transfer.debit.clone(),
+            transfer.credit.clone(),
+            debit_shares,
+            credit_shares,
+            &self.sk_set.public_keys(),
+            self.signing.payload_format(),
+        )
+    }
+
+    fn group_sign<T: serde::Serialize>(&self, value: &T) -> Result<Signature> {
+        let bytes = bincode::serialize(value).map_err(|e| Error::NetworkOther(e.to_string()))?;
+        let shares: BTreeMap<_, _> = vec![(0, self.sk_set.secret_key_share(0).sign(&bytes))]
+            .into_iter()
+            .collect();
+        let signature = combine_shares(&self.sk_set.public_keys(), &shares, &bytes)?;
+        Ok(Signature::Bls(signature))
+    }
+}
+
+fn get_signed_transfer(
+    keypair: &Keypair,
+    counter: u64,
+    amount: u64,
+    recipient: PublicKey,
+) -> Result<SignedTransfer> {
+    let debit = Debit {
+        id: Dot::new(keypair.public_key(), counter),
+        amount: Money::from_nano(amount),
+    };
+    let credit = Credit {
+        id: debit.credit_id()?,
+        recipient,
+        amount: debit.amount(),
+        msg: "replay".to_string(),
+    };
+    let debit_bytes = bincode::serialize(&debit).map_err(|e| Error::NetworkOther(e.to_string()))?;
+    let credit_bytes =
+        bincode::serialize(&credit).map_err(|e| Error::NetworkOther(e.to_string()))?;
+    Ok(SignedTransfer {
+        debit: SignedDebit {
+            actor_signature: keypair.sign(&debit_bytes),
+            debit,
+        },
+        credit: SignedCredit {
+            actor_signature: keypair.sign(&credit_bytes),
+            credit,
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replays_hundreds_of_events() -> Result<()> {
+        let mut harness = ReplayHarness::new(42)?;
+
+        harness.run(300)?;
+
+        assert!(harness.events().len() >= 300);
+        assert!(harness.replica().is_genesised());
+        Ok(())
+    }
+
+    #[test]
+    fn replays_the_same_sequence_for_a_seed() -> Result<()> {
+        let mut harness = ReplayHarness::new(7)?;
+        let mut same = ReplayHarness::new(7)?;
+
+        harness.run(50)?;
+        same.run(50)?;
+
+        assert_eq!(harness.replica().balance(), same.replica().balance());
+        assert_eq!(harness.events().len(), same.events().len());
+        Ok(())
+    }
+}
@@ -14,8 +14,8 @@ use log::debug;
 #[cfg(feature = "simulated-payouts")]
 use sn_data_types::Credit;
 use sn_data_types::{
-    CreditAgreementProof, Debit, Error, KnownGroupAdded, Money, PublicKey, ReplicaEvent, Result,
-    SignatureShare, SignedCredit, SignedDebit, TransferAgreementProof, TransferPropagated,
+    CreditAgreementProof, CreditId, Debit, Error, KnownGroupAdded, Money, PublicKey, ReplicaEvent,
+    Result, SignatureShare, SignedCredit, SignedDebit, TransferAgreementProof, TransferPropagated,
     TransferRegistered, TransferValidated,
 };
 use std::collections::{HashMap, HashSet};
@@ -129,6 +129,10 @@ impl Replica {
         if !self.wallets.is_empty() {
             return Err(Error::InvalidOperation);
         }
+        // Genesis always has the canonical default credit id.
+        if credit_proof.id() != &CreditId::default() {
+            return Err(Error::InvalidOperation);
+        }
         self.receive_propagated(credit_proof, f)
     }
 
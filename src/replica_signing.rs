@@ -6,10 +6,21 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{Outcome, TernaryResult};
+use super::{
+    attestation::{BalanceClaim, SignedWalletSummary, WalletSummary},
+    consistency::check_transfer_consistency,
+    payload::{signing_payload, signing_payload_into, PayloadFormat},
+    proofs::credit_proof_from_transfer,
+    wallet::WalletSnapshot,
+    BincodeCodec, Codec, KeyIndex, Outcome, TernaryResult,
+};
+use serde::Serialize;
 use sn_data_types::{
-    CreditAgreementProof, Error, SignatureShare, SignedCredit, SignedDebit, SignedTransfer,
+    CreditAgreementProof, Error, Money, PublicKey, Result, Signature, SignatureShare, SignedCredit,
+    SignedDebit, SignedTransfer, TransferAgreementProof, TransferValidated,
 };
+use std::convert::TryFrom;
+use std::sync::Arc;
 use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKeyShare};
 
 /// The Replica is the part of an AT2 system
@@ -20,7 +31,7 @@ use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKeyShare};
 /// from the group, i.e. signatures from a quorum of its peers.
 /// Replicas don't initiate transfers or drive the algo - only Actors do.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ReplicaSigning {
+pub struct ReplicaSigning<C: Codec = BincodeCodec> {
     /// The public key share of this Replica.
     id: PublicKeyShare,
     /// Secret key share.
@@ -29,6 +40,10 @@ pub struct ReplicaSigning {
     key_index: usize,
     /// The PK set of our peer Replicas.
     peer_replicas: PublicKeySet,
+    /// The format of what we sign.
+    format: PayloadFormat,
+    /// The encoding of what we sign.
+    codec: Arc<C>,
     // /// PK sets of other known groups of Replicas.
     // other_groups: HashSet<PublicKeySet>,
 }
@@ -37,24 +52,119 @@ impl ReplicaSigning {
     /// A new instance
     pub fn new(
         secret_key: SecretKeyShare,
-        key_index: usize,
+        key_index: KeyIndex,
         peer_replicas: PublicKeySet,
         //other_groups: HashSet<PublicKeySet>,
+    ) -> Self {
+        Self::new_with_codec(secret_key, key_index, peer_replicas, Arc::new(BincodeCodec))
+    }
+
+    /// A new instance, like `new`, but rejecting a secret key share
+    /// that is not the one at the key index of the peers' key set,
+    /// since it would sign shares that never combine with those of the peers.
+    pub fn try_new(
+        secret_key: SecretKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+    ) -> Result<Self> {
+        check_key_share(&secret_key, key_index.0, &peer_replicas)?;
+        Ok(Self::new(secret_key, key_index, peer_replicas))
+    }
+
+    /// A new instance, like `try_new`, but also attesting that the signer
+    /// produces shares that verify under its key share in the peers' key set,
+    /// by signing a known challenge and verifying the share.
+    /// A signer failing either check is rejected with `Error::InvalidSignature`.
+    pub fn new_attested(
+        secret_key: SecretKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+    ) -> Result<Self> {
+        let instance = Self::try_new(secret_key, key_index, peer_replicas)
+            .map_err(|_| Error::InvalidSignature)?;
+        let share = instance.sign(&mut Vec::new(), &ATTESTATION_CHALLENGE)?;
+        let bytes = signing_payload(
+            instance.codec.as_ref(),
+            &instance.format,
+            &ATTESTATION_CHALLENGE,
+        )?;
+        if instance
+            .peer_replicas
+            .public_key_share(share.index)
+            .verify(&share.share, bytes)
+        {
+            Ok(instance)
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+}
+
+impl<C: Codec> ReplicaSigning<C> {
+    /// A new instance signing values encoded with the codec.
+    /// Pass the same codec instance to the verifying `WalletReplica`.
+    pub fn new_with_codec(
+        secret_key: SecretKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+        codec: Arc<C>,
     ) -> Self {
         let id = secret_key.public_key_share();
         Self {
             secret_key,
             id,
-            key_index,
+            key_index: key_index.0,
             peer_replicas,
+            format: PayloadFormat::default(),
+            codec,
             //other_groups,
         }
     }
 
+    /// Sets the version of the wire format to sign under.
+    /// All Replicas verifying our signatures must use the same version.
+    pub fn set_wire_version(&mut self, wire_version: u8) {
+        self.format.wire_version = wire_version;
+    }
+
+    /// Sets the network that our signatures are bound to.
+    /// All Replicas verifying our signatures must be of the same network.
+    pub fn set_network_id(&mut self, network_id: Option<[u8; 32]>) {
+        self.format.network_id = network_id;
+    }
+
+    /// Sets the context, i.e. the transfer system, that our signatures are bound to.
+    /// All Replicas verifying our signatures must be of the same context.
+    pub fn set_context(&mut self, context: Vec<u8>) {
+        self.format.context = context;
+    }
+
+    /// Replaces the secret key share, f.ex. when it is reloaded from storage,
+    /// keeping the peers and key index. The share must be the one of our
+    /// index in the peers' key set, else it is rejected and nothing is changed.
+    pub fn set_secret_key(&mut self, secret_key: SecretKeyShare) -> Result<()> {
+        check_key_share(&secret_key, self.key_index, &self.peer_replicas)?;
+        self.id = secret_key.public_key_share();
+        self.secret_key = secret_key;
+        Ok(())
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Queries ----------------------------------
     /// -----------------------------------------------------------------
 
+    /// The index of this Replica key share, in the group set.
+    /// This is also the index of every `SignatureShare` we produce,
+    /// i.e. the one to combine it with the shares of our peers under.
+    pub fn key_index(&self) -> usize {
+        self.key_index
+    }
+
+    /// The format of what we sign, f.ex. for verifying our wallet summaries.
+    pub fn payload_format(&self) -> &PayloadFormat {
+        &self.format
+    }
+
     /// Get the replica's PK set
     pub fn replicas_pk_set(&self) -> PublicKeySet {
         self.peer_replicas.clone()
@@ -68,46 +178,503 @@ impl ReplicaSigning {
         &self,
         signed_transfer: &SignedTransfer,
     ) -> Outcome<(SignatureShare, SignatureShare)> {
-        let replica_debit_sig = self.sign_validated_debit(&signed_transfer.debit)?;
-        let replica_credit_sig = self.sign_validated_credit(&signed_transfer.credit)?;
-        if let Some(rds) = replica_debit_sig {
-            if let Some(rcs) = replica_credit_sig {
-                return Outcome::success((rds, rcs));
-            }
+        // Never sign a malformed transfer.
+        check_transfer_consistency(signed_transfer)?;
+        if signed_transfer.debit.amount() == Money::zero() {
+            return Outcome::rejected(Error::Unexpected(
+                "Transfer amount must be more than zero.".to_string(),
+            ));
         }
-        Outcome::rejected(Error::InvalidSignature)
+
+        // One buffer is reused for both payloads.
+        let mut buf = Vec::new();
+        let replica_debit_sig = self.sign(&mut buf, &signed_transfer.debit)?;
+        let replica_credit_sig = self.sign(&mut buf, &signed_transfer.credit)?;
+        Outcome::success((replica_debit_sig, replica_credit_sig))
     }
 
-    ///
+    /// Signs the transfer, like `sign_transfer`, and returns the
+    /// signature shares in the TransferValidated event, ready to be applied.
+    pub fn sign_transfer_validated(
+        &self,
+        signed_transfer: &SignedTransfer,
+    ) -> Outcome<TransferValidated> {
+        let (replica_debit_sig, replica_credit_sig) = match self.sign_transfer(signed_transfer)? {
+            Some(shares) => shares,
+            None => return Outcome::no_change(),
+        };
+        Outcome::success(TransferValidated {
+            signed_debit: signed_transfer.debit.clone(),
+            signed_credit: signed_transfer.credit.clone(),
+            replica_debit_sig,
+            replica_credit_sig,
+            replicas: self.peer_replicas.clone(),
+        })
+    }
+
+    /// Signs the validated debit, with the share index being our `key_index`.
     pub fn sign_validated_debit(&self, debit: &SignedDebit) -> Outcome<SignatureShare> {
-        match bincode::serialize(debit) {
-            Err(_) => Err(Error::NetworkOther("Could not serialise debit".into())),
-            Ok(data) => Outcome::success(SignatureShare {
-                index: self.key_index,
-                share: self.secret_key.sign(data),
-            }),
-        }
+        Outcome::success(self.sign(&mut Vec::new(), debit)?)
     }
 
     ///
     pub fn sign_validated_credit(&self, credit: &SignedCredit) -> Outcome<SignatureShare> {
-        match bincode::serialize(credit) {
-            Err(_) => Err(Error::NetworkOther("Could not serialise credit".into())),
-            Ok(data) => Outcome::success(SignatureShare {
-                index: self.key_index,
-                share: self.secret_key.sign(data),
-            }),
-        }
+        Outcome::success(self.sign(&mut Vec::new(), credit)?)
     }
 
     ///
     pub fn sign_credit_proof(&self, proof: &CreditAgreementProof) -> Outcome<SignatureShare> {
-        match bincode::serialize(proof) {
-            Err(_) => Err(Error::NetworkOther("Could not serialise proof".into())),
-            Ok(data) => Outcome::success(SignatureShare {
-                index: self.key_index,
-                share: self.secret_key.sign(data),
-            }),
+        Outcome::success(self.sign(&mut Vec::new(), proof)?)
+    }
+
+    /// Signs a summary of the wallet, for gossiping to peers,
+    /// see `verify_wallet_summary`.
+    pub fn sign_wallet_summary(&self, snapshot: &WalletSnapshot) -> Outcome<SignedWalletSummary> {
+        let summary = WalletSummary::try_from(snapshot)?;
+        let replica_sig = self.sign(&mut Vec::new(), &summary)?;
+        Outcome::success(SignedWalletSummary {
+            summary,
+            replica_sig,
+        })
+    }
+
+    /// Signs the balance of the wallet as of the debit counter, for a client
+    /// to present to third parties, see `combine_balance_attestation`.
+    pub fn sign_balance_attestation(
+        &self,
+        balance: Money,
+        wallet: PublicKey,
+        as_of_counter: u64,
+    ) -> Outcome<SignatureShare> {
+        let claim = BalanceClaim {
+            wallet,
+            balance,
+            as_of_counter,
+        };
+        Outcome::success(self.sign(&mut Vec::new(), &claim)?)
+    }
+
+    /// Builds the proof for propagating the credit of an agreed transfer,
+    /// carrying the signature share of this Replica.
+    /// The proofs of a quorum of Replicas are then combined
+    /// into the one signed by the group before propagation, see `combine_credit_proofs`.
+    pub fn build_credit_proof(
+        &self,
+        transfer_proof: &TransferAgreementProof,
+    ) -> Outcome<CreditAgreementProof> {
+        // Only propagate what our group has agreed on.
+        let credit_bytes = signing_payload(
+            self.codec.as_ref(),
+            &self.format,
+            &transfer_proof.signed_credit,
+        )
+        .map_err(|_| Error::NetworkOther("Could not serialise credit".into()))?;
+        let our_key = PublicKey::Bls(self.peer_replicas.public_key());
+        if our_key
+            .verify(&transfer_proof.credit_sig, &credit_bytes)
+            .is_err()
+        {
+            return Outcome::rejected(Error::InvalidSignature);
         }
+        let share = self.sign(&mut Vec::new(), &transfer_proof.signed_credit)?;
+        Outcome::success(credit_proof_from_transfer(
+            transfer_proof,
+            Signature::BlsShare(share),
+            self.peer_replicas.clone(),
+        ))
+    }
+
+    /// Re-attests a credit proof of an old key set under our key set,
+    /// f.ex. after a rotation, so that it no longer relies on the old key.
+    /// The shares of a quorum of Replicas are combined into
+    /// the new signature of the proof.
+    /// The old key set must be one of the trusted keys, f.ex. our past keys,
+    /// so that a proof of a made up key set is never re-signed.
+    pub fn resign_credit_proof(
+        &self,
+        old_proof: &CreditAgreementProof,
+        trusted_keys: &[PublicKey],
+    ) -> Outcome<SignatureShare> {
+        let old_key = PublicKey::Bls(old_proof.debiting_replicas_keys.public_key());
+        if !trusted_keys.contains(&old_key) {
+            return Outcome::rejected(Error::InvalidSignature);
+        }
+        let credit_bytes =
+            signing_payload(self.codec.as_ref(), &self.format, &old_proof.signed_credit)
+                .map_err(|_| Error::NetworkOther("Could not serialise credit".into()))?;
+        if old_key
+            .verify(&old_proof.debiting_replicas_sig, &credit_bytes)
+            .is_err()
+        {
+            return Outcome::rejected(Error::InvalidSignature);
+        }
+        Outcome::success(self.sign(&mut Vec::new(), &old_proof.signed_credit)?)
+    }
+
+    /// -----------------------------------------------------------------
+    /// ---------------------- Private methods --------------------------
+    /// -----------------------------------------------------------------
+
+    /// Signs the payload of the value under our format,
+    /// using the provided buffer for the serialisation.
+    fn sign<T: Serialize>(&self, buf: &mut Vec<u8>, value: &T) -> Result<SignatureShare> {
+        signing_payload_into(self.codec.as_ref(), buf, &self.format, value)?;
+        Ok(SignatureShare {
+            index: self.key_index,
+            share: self.secret_key.sign(buf.as_slice()),
+        })
+    }
+}
+
+/// What a signer signs for attesting its key share, see `ReplicaSigning::new_attested`.
+const ATTESTATION_CHALLENGE: &str = "sn_transfers replica signer attestation";
+
+/// Checks that the secret key share is the one at the index of the key set.
+fn check_key_share(
+    secret_key: &SecretKeyShare,
+    key_index: usize,
+    peer_replicas: &PublicKeySet,
+) -> Result<()> {
+    if secret_key.public_key_share() == peer_replicas.public_key_share(key_index) {
+        Ok(())
+    } else {
+        Err(Error::NetworkOther(format!(
+            "The key share does not belong to index {} of our peers' key set.",
+            key_index
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{combine_credit_proofs, WalletReplica};
+    use crdts::Dot;
+    use sn_data_types::{Credit, Debit, Keypair};
+    use std::collections::BTreeMap;
+    use threshold_crypto::{SecretKey, SecretKeySet};
+
+    #[test]
+    fn signs_consistent_transfer() -> Result<()> {
+        let replica = get_replica(&get_sk_set());
+        let transfer = get_signed_transfer(10)?;
+        assert!(replica.sign_transfer(&transfer)?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn returns_validated_event_of_signed_transfer() -> Result<()> {
+        let replica = get_replica(&get_sk_set());
+        let transfer = get_signed_transfer(10)?;
+
+        let event = replica
+            .sign_transfer_validated(&transfer)?
+            .ok_or_else(|| Error::Unexpected("Unexpected outcome".to_string()))?;
+
+        assert_eq!(
+            event.signed_debit.debit.id.counter,
+            transfer.debit.debit.id.counter
+        );
+        assert_eq!(event.replica_debit_sig.index, replica.key_index());
+        Ok(())
+    }
+
+    #[test]
+    fn stamps_shares_with_key_index() -> Result<()> {
+        let sk_set = get_sk_set();
+        let peer_replicas = sk_set.public_keys();
+        let replica = ReplicaSigning::new(
+            sk_set.secret_key_share(2),
+            KeyIndex(2),
+            peer_replicas.clone(),
+        );
+        let transfer = get_signed_transfer(10)?;
+
+        let share = replica
+            .sign_validated_debit(&transfer.debit)?
+            .ok_or_else(|| Error::Unexpected("Unexpected outcome".to_string()))?;
+
+        assert_eq!(share.index, replica.key_index());
+        let payload = signing_payload(&BincodeCodec, replica.payload_format(), &transfer.debit)?;
+        assert!(peer_replicas
+            .public_key_share(share.index)
+            .verify(&share.share, payload));
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_sign_mismatched_amounts() -> Result<()> {
+        let replica = get_replica(&get_sk_set());
+        let mut transfer = get_signed_transfer(10)?;
+        transfer.credit.credit.amount = Money::from_nano(11);
+        assert!(replica.sign_transfer(&transfer).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_sign_mismatched_credit_id() -> Result<()> {
+        let replica = get_replica(&get_sk_set());
+        let mut transfer = get_signed_transfer(10)?;
+        transfer.credit.credit.id = Default::default();
+        assert!(replica.sign_transfer(&transfer).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn builds_credit_proof_from_transfer_proof() -> Result<()> {
+        let sk_set = get_sk_set();
+        let replica = get_replica(&sk_set);
+        let transfer_proof = get_transfer_proof(&sk_set, get_signed_transfer(10)?)?;
+
+        let credit_proof = replica
+            .build_credit_proof(&transfer_proof)?
+            .ok_or_else(|| Error::Unexpected("Unexpected outcome".to_string()))?;
+
+        assert_eq!(credit_proof.signed_credit, transfer_proof.signed_credit);
+        assert_eq!(credit_proof.debiting_replicas_keys, sk_set.public_keys());
+        Ok(())
+    }
+
+    #[test]
+    fn combined_credit_proofs_are_received() -> Result<()> {
+        let sk_set = get_sk_set();
+        let peer_replicas = sk_set.public_keys();
+        let transfer_proof = get_transfer_proof(&sk_set, get_signed_transfer(10)?)?;
+        let proofs = (0..=sk_set.threshold())
+            .map(|index| {
+                ReplicaSigning::new(
+                    sk_set.secret_key_share(index),
+                    KeyIndex(index),
+                    peer_replicas.clone(),
+                )
+                .build_credit_proof(&transfer_proof)?
+                .ok_or_else(|| Error::Unexpected("Unexpected outcome".to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let credit_proof = combine_credit_proofs(
+            &BincodeCodec,
+            &proofs,
+            &peer_replicas,
+            &PayloadFormat::default(),
+        )?;
+
+        let recipient = credit_proof.recipient();
+        let wallet_replica = WalletReplica::from_history(
+            recipient,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            vec![],
+        )?;
+        let result = wallet_replica.receive_propagated(&credit_proof, || Err(Error::NoSuchSender));
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn resigned_credit_proof_verifies_under_new_key_set() -> Result<()> {
+        let old_set = get_sk_set();
+        let new_set = get_sk_set();
+        let transfer = get_signed_transfer(10)?;
+        let credit_bytes =
+            bincode::serialize(&transfer.credit).map_err(|e| Error::from(e.to_string()))?;
+        let old_proof = CreditAgreementProof {
+            signed_credit: transfer.credit,
+            debiting_replicas_sig: sign_with_set(&old_set, &credit_bytes)?,
+            debiting_replicas_keys: old_set.public_keys(),
+        };
+
+        let trusted_keys = [PublicKey::Bls(old_set.public_keys().public_key())];
+
+        let mut shares = BTreeMap::new();
+        for index in 0..=new_set.threshold() {
+            let replica = ReplicaSigning::new(
+                new_set.secret_key_share(index),
+                KeyIndex(index),
+                new_set.public_keys(),
+            );
+            let share = replica
+                .resign_credit_proof(&old_proof, &trusted_keys)?
+                .ok_or_else(|| Error::Unexpected("Unexpected outcome".to_string()))?;
+            let _ = shares.insert(share.index, share.share);
+        }
+        let signature = new_set
+            .public_keys()
+            .combine_signatures(&shares)
+            .map_err(|e| Error::Unexpected(e.to_string()))?;
+
+        assert!(new_set
+            .public_keys()
+            .public_key()
+            .verify(&signature, &credit_bytes));
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_to_resign_credit_proof_of_untrusted_key_set() -> Result<()> {
+        let old_set = get_sk_set();
+        let foreign_set = get_sk_set();
+        let transfer = get_signed_transfer(10)?;
+        let credit_bytes =
+            bincode::serialize(&transfer.credit).map_err(|e| Error::from(e.to_string()))?;
+        let foreign_proof = CreditAgreementProof {
+            signed_credit: transfer.credit,
+            debiting_replicas_sig: sign_with_set(&foreign_set, &credit_bytes)?,
+            debiting_replicas_keys: foreign_set.public_keys(),
+        };
+        let replica = get_replica(&get_sk_set());
+
+        let result = replica.resign_credit_proof(
+            &foreign_proof,
+            &[PublicKey::Bls(old_set.public_keys().public_key())],
+        );
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn signs_with_replaced_secret_key() -> Result<()> {
+        let sk_set = get_sk_set();
+        let mut replica = ReplicaSigning::new(
+            get_sk_set().secret_key_share(0),
+            KeyIndex(0),
+            sk_set.public_keys(),
+        );
+        let transfer = get_signed_transfer(10)?;
+
+        replica.set_secret_key(sk_set.secret_key_share(0))?;
+        let (debit_sig, _) = replica
+            .sign_transfer(&transfer)?
+            .ok_or_else(|| Error::Unexpected("Unexpected outcome".to_string()))?;
+
+        let debit_bytes =
+            bincode::serialize(&transfer.debit).map_err(|e| Error::from(e.to_string()))?;
+        assert!(sk_set
+            .public_keys()
+            .public_key_share(0)
+            .verify(&debit_sig.share, &debit_bytes));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_secret_key_of_other_index() {
+        let sk_set = get_sk_set();
+        let mut replica = get_replica(&sk_set);
+
+        let result = replica.set_secret_key(sk_set.secret_key_share(1));
+
+        assert!(result.is_err());
+        assert_eq!(replica, get_replica(&sk_set));
+    }
+
+    #[test]
+    fn fails_construction_with_key_share_of_other_index() {
+        let sk_set = get_sk_set();
+
+        let result = ReplicaSigning::try_new(
+            sk_set.secret_key_share(1),
+            KeyIndex(0),
+            sk_set.public_keys(),
+        );
+
+        assert!(result.is_err());
+        assert!(ReplicaSigning::try_new(
+            sk_set.secret_key_share(0),
+            KeyIndex(0),
+            sk_set.public_keys()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn fails_attestation_of_wrong_key_share() {
+        let sk_set = get_sk_set();
+
+        let result = ReplicaSigning::new_attested(
+            get_sk_set().secret_key_share(0),
+            KeyIndex(0),
+            sk_set.public_keys(),
+        );
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        assert!(ReplicaSigning::new_attested(
+            sk_set.secret_key_share(0),
+            KeyIndex(0),
+            sk_set.public_keys()
+        )
+        .is_ok());
+    }
+
+    fn get_sk_set() -> SecretKeySet {
+        SecretKeySet::random(1, &mut rand::thread_rng())
+    }
+
+    fn get_replica(sk_set: &SecretKeySet) -> ReplicaSigning {
+        ReplicaSigning::new(
+            sk_set.secret_key_share(0),
+            KeyIndex(0),
+            sk_set.public_keys(),
+        )
+    }
+
+    fn get_transfer_proof(
+        sk_set: &SecretKeySet,
+        transfer: SignedTransfer,
+    ) -> Result<TransferAgreementProof> {
+        let debit_bytes =
+            bincode::serialize(&transfer.debit).map_err(|e| Error::from(e.to_string()))?;
+        let credit_bytes =
+            bincode::serialize(&transfer.credit).map_err(|e| Error::from(e.to_string()))?;
+        Ok(TransferAgreementProof {
+            debit_sig: sign_with_set(sk_set, &debit_bytes)?,
+            credit_sig: sign_with_set(sk_set, &credit_bytes)?,
+            signed_debit: transfer.debit,
+            signed_credit: transfer.credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        })
+    }
+
+    /// Combines a quorum of shares into the group signature.
+    fn sign_with_set(sk_set: &SecretKeySet, data: &[u8]) -> Result<Signature> {
+        let shares: BTreeMap<_, _> = (0..=sk_set.threshold())
+            .map(|i| (i, sk_set.secret_key_share(i).sign(data)))
+            .collect();
+        let signature = sk_set
+            .public_keys()
+            .combine_signatures(&shares)
+            .map_err(|e| Error::Unexpected(e.to_string()))?;
+        Ok(Signature::Bls(signature))
+    }
+
+    fn get_signed_transfer(amount: u64) -> Result<SignedTransfer> {
+        let keypair = Keypair::new_ed25519(&mut rand::thread_rng());
+        let debit = Debit {
+            id: Dot::new(keypair.public_key(), 0),
+            amount: Money::from_nano(amount),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient: get_random_pk(),
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        let debit_bytes = bincode::serialize(&debit).map_err(|e| Error::from(e.to_string()))?;
+        let credit_bytes = bincode::serialize(&credit).map_err(|e| Error::from(e.to_string()))?;
+        Ok(SignedTransfer {
+            debit: SignedDebit {
+                debit,
+                actor_signature: keypair.sign(&debit_bytes),
+            },
+            credit: SignedCredit {
+                credit,
+                actor_signature: keypair.sign(&credit_bytes),
+            },
+        })
+    }
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
     }
 }
@@ -0,0 +1,86 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::wallet_replica::WalletReplica;
+use sn_data_types::{Credit, Debit, Result};
+
+/// A Replica that can simulate transfers, creating money without proofs.
+/// Only this type exposes it, so that a `WalletReplica` never does,
+/// even when the feature is enabled by another crate:
+///
+/// ```compile_fail
+/// fn print_money(replica: &mut sn_transfers::WalletReplica, credit: sn_data_types::Credit) {
+///     replica.credit_without_proof(credit).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedReplica {
+    replica: WalletReplica,
+}
+
+impl From<WalletReplica> for SimulatedReplica {
+    fn from(replica: WalletReplica) -> Self {
+        Self { replica }
+    }
+}
+
+impl SimulatedReplica {
+    /// The simulating Replica.
+    pub fn replica(&self) -> &WalletReplica {
+        &self.replica
+    }
+
+    /// Test-helper API to simulate Client CREDIT Transfers.
+    pub fn credit_without_proof(&mut self, credit: Credit) -> Result<()> {
+        self.replica.credit_without_proof(credit)
+    }
+
+    /// Test-helper API to simulate Client DEBIT Transfers.
+    pub fn debit_without_proof(&mut self, debit: Debit) -> Result<()> {
+        self.replica.debit_without_proof(debit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::KeyIndex;
+    use crdts::Dot;
+    use sn_data_types::{Money, PublicKey};
+    use threshold_crypto::{SecretKey, SecretKeySet};
+    use xor_name::XorName;
+
+    #[test]
+    fn simulates_transfers() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let peer_replicas = sk_set.public_keys();
+        let id = PublicKey::from(SecretKey::random().public_key());
+        let replica = WalletReplica::from_history(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            vec![],
+        )?;
+        let mut simulated = SimulatedReplica::from(replica);
+
+        simulated.credit_without_proof(Credit {
+            id: XorName::random(),
+            recipient: id,
+            amount: Money::from_nano(100),
+            msg: "asdf".to_string(),
+        })?;
+        simulated.debit_without_proof(Debit {
+            id: Dot::new(id, 0),
+            amount: Money::from_nano(10),
+        })?;
+
+        assert_eq!(simulated.replica().balance(), Money::from_nano(90));
+        simulated.replica().audit_balance()
+    }
+}
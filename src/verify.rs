@@ -0,0 +1,253 @@
+// Copyright 2020 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    payload::{signing_payload, PayloadFormat},
+    BincodeCodec, Codec,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sn_data_types::{
+    CreditAgreementProof, Error, PublicKey, Result, Signature, TransferAgreementProof,
+};
+use threshold_crypto::PublicKeySet;
+
+/// Verifies that the credit proof is signed by any of the trusted keys.
+/// Needs no replica state, so the proof can be verified offline, f.ex. by an auditor.
+pub fn verify_credit_proof(proof: &CreditAgreementProof, trusted_keys: &[PublicKey]) -> Result<()> {
+    verify_credit_proof_under(
+        &BincodeCodec,
+        proof,
+        trusted_keys,
+        &PayloadFormat::default(),
+    )
+}
+
+/// Verifies the credit proof as signed under the given codec and format.
+pub(crate) fn verify_credit_proof_under<C: Codec>(
+    codec: &C,
+    proof: &CreditAgreementProof,
+    trusted_keys: &[PublicKey],
+    format: &PayloadFormat,
+) -> Result<()> {
+    let credit_bytes = match signing_payload(codec, format, &proof.signed_credit) {
+        Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
+        Ok(bytes) => bytes,
+    };
+    let signed_by_trusted_key = trusted_keys.iter().any(|key| {
+        key.verify(&proof.debiting_replicas_sig, &credit_bytes)
+            .is_ok()
+    });
+    if signed_by_trusted_key {
+        Ok(())
+    } else {
+        // If we don't know the public key this was signed with, we won't consider it valid.
+        Err(Error::InvalidSignature)
+    }
+}
+
+/// Verifies that the propagated credit proof is signed by any of the accepted
+/// groups of Replicas. Needs no replica, f.ex. for a stateless verification service.
+pub fn verify_propagated_with_keys(
+    proof: &CreditAgreementProof,
+    accept: &[PublicKeySet],
+) -> Result<()> {
+    verify_propagated_with_keys_under(&BincodeCodec, proof, accept, &PayloadFormat::default())
+}
+
+/// Verifies the propagated credit proof as signed under the given codec and format.
+pub(crate) fn verify_propagated_with_keys_under<C: Codec>(
+    codec: &C,
+    proof: &CreditAgreementProof,
+    accept: &[PublicKeySet],
+    format: &PayloadFormat,
+) -> Result<()> {
+    let keys: Vec<_> = accept
+        .iter()
+        .map(|set| PublicKey::Bls(set.public_key()))
+        .collect();
+    verify_credit_proof_under(codec, proof, &keys, format)
+}
+
+/// Verifies that both the debit and the credit of the transfer proof
+/// are signed by any of the trusted keys. Needs no replica state.
+pub fn verify_transfer_proof(
+    proof: &TransferAgreementProof,
+    trusted_keys: &[PublicKey],
+) -> Result<()> {
+    let format = PayloadFormat::default();
+    let debit_bytes = match signing_payload(&BincodeCodec, &format, &proof.signed_debit) {
+        Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
+        Ok(bytes) => bytes,
+    };
+    let credit_bytes = match signing_payload(&BincodeCodec, &format, &proof.signed_credit) {
+        Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
+        Ok(bytes) => bytes,
+    };
+    let signed_by_trusted_key = trusted_keys.iter().any(|key| {
+        key.verify(&proof.debit_sig, &debit_bytes).is_ok()
+            && key.verify(&proof.credit_sig, &credit_bytes).is_ok()
+    });
+    if signed_by_trusted_key {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+/// Parses a signature received from the wire, rejecting any encoding
+/// other than the canonical one, so that a signature cannot be
+/// re-encoded into a seemingly different but still valid one.
+pub fn signature_from_canonical_bytes(bytes: &[u8]) -> Result<Signature> {
+    from_canonical_bytes(bytes)
+}
+
+/// Parses a credit proof received from the wire, rejecting
+/// non-canonical encodings, including those of its signatures.
+pub fn credit_proof_from_canonical_bytes(bytes: &[u8]) -> Result<CreditAgreementProof> {
+    from_canonical_bytes(bytes)
+}
+
+/// Parses a transfer proof received from the wire, rejecting
+/// non-canonical encodings, including those of its signatures.
+pub fn transfer_proof_from_canonical_bytes(bytes: &[u8]) -> Result<TransferAgreementProof> {
+    from_canonical_bytes(bytes)
+}
+
+// The encoding is canonical only if it is exactly the re-encoding of what it decodes to.
+fn from_canonical_bytes<T: DeserializeOwned + Serialize>(bytes: &[u8]) -> Result<T> {
+    let value: T = bincode::deserialize(bytes).map_err(|_| Error::InvalidSignature)?;
+    match bincode::serialize(&value) {
+        Ok(canonical) if canonical.as_slice() == bytes => Ok(value),
+        _ => Err(Error::InvalidSignature),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use sn_data_types::{Credit, Money, Signature, SignedCredit};
+    use threshold_crypto::{SecretKey, SecretKeySet};
+
+    #[test]
+    fn verifies_against_one_of_the_trusted_keys() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set)?;
+        let trusted_keys = vec![
+            get_random_pk(),
+            PublicKey::Bls(sk_set.public_keys().public_key()),
+            get_random_pk(),
+        ];
+
+        verify_credit_proof(&proof, &trusted_keys)
+    }
+
+    #[test]
+    fn fails_when_no_trusted_key_matches() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set)?;
+        let trusted_keys = vec![get_random_pk(), get_random_pk()];
+
+        let result = verify_credit_proof(&proof, &trusted_keys);
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        assert!(matches!(
+            verify_credit_proof(&proof, &[]),
+            Err(Error::InvalidSignature)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn verifies_propagated_against_accepted_groups() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let other_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set)?;
+
+        verify_propagated_with_keys(&proof, &[other_set.public_keys(), sk_set.public_keys()])
+    }
+
+    #[test]
+    fn fails_propagated_when_no_group_is_accepted() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let other_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set)?;
+
+        let result = verify_propagated_with_keys(&proof, &[other_set.public_keys()]);
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn parses_canonical_encodings() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set)?;
+        let sig_bytes = bincode::serialize(&proof.debiting_replicas_sig)
+            .map_err(|e| Error::from(e.to_string()))?;
+        let proof_bytes = bincode::serialize(&proof).map_err(|e| Error::from(e.to_string()))?;
+
+        assert_eq!(
+            signature_from_canonical_bytes(&sig_bytes)?,
+            proof.debiting_replicas_sig
+        );
+        assert_eq!(credit_proof_from_canonical_bytes(&proof_bytes)?, proof);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_non_canonical_encodings() -> Result<()> {
+        let sk_set = SecretKeySet::random(0, &mut rand::thread_rng());
+        let proof = get_credit_proof(&sk_set)?;
+        // Trailing bytes still decode to the same signature, but are not canonical.
+        let mut sig_bytes = bincode::serialize(&proof.debiting_replicas_sig)
+            .map_err(|e| Error::from(e.to_string()))?;
+        sig_bytes.push(0);
+        let mut proof_bytes = bincode::serialize(&proof).map_err(|e| Error::from(e.to_string()))?;
+        proof_bytes.push(0);
+
+        assert!(matches!(
+            signature_from_canonical_bytes(&sig_bytes),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            credit_proof_from_canonical_bytes(&proof_bytes),
+            Err(Error::InvalidSignature)
+        ));
+        Ok(())
+    }
+
+    fn get_credit_proof(sk_set: &SecretKeySet) -> Result<CreditAgreementProof> {
+        let credit = Credit {
+            id: Default::default(),
+            recipient: get_random_pk(),
+            amount: Money::from_nano(10),
+            msg: "credit".to_string(),
+        };
+        let credit_bytes = bincode::serialize(&credit).map_err(|e| Error::from(e.to_string()))?;
+        let signed_credit = SignedCredit {
+            credit,
+            actor_signature: Signature::Bls(SecretKey::random().sign(&credit_bytes)),
+        };
+        let signed_credit_bytes =
+            bincode::serialize(&signed_credit).map_err(|e| Error::from(e.to_string()))?;
+        let share = sk_set.secret_key_share(0).sign(&signed_credit_bytes);
+        let signature = sk_set
+            .public_keys()
+            .combine_signatures(vec![(0, &share)])
+            .map_err(|e| Error::from(e.to_string()))?;
+        Ok(CreditAgreementProof {
+            signed_credit,
+            debiting_replicas_sig: Signature::Bls(signature),
+            debiting_replicas_keys: sk_set.public_keys(),
+        })
+    }
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
+    }
+}
@@ -10,13 +10,28 @@ use log::debug;
 use sn_data_types::{Credit, CreditId, Debit, Error, Money, PublicKey, Result};
 use std::collections::HashSet;
 
+/// The balance and versions of a wallet at some point in time.
 #[derive(Debug, Clone)]
 pub struct WalletSnapshot {
+    /// The balance of the wallet.
     pub balance: Money,
+    /// The next debit version, i.e. the number of debits applied.
     pub debit_version: u64,
+    /// The ids of all credits applied.
     pub credit_ids: HashSet<CreditId>,
 }
 
+impl WalletSnapshot {
+    /// The serialised snapshot, with the credit ids sorted,
+    /// so that equal snapshots always give the same bytes, f.ex. for digests.
+    pub fn canonical_bytes(&self) -> Result<Vec<u8>> {
+        let mut credit_ids: Vec<_> = self.credit_ids.iter().collect();
+        credit_ids.sort();
+        bincode::serialize(&(self.balance, self.debit_version, credit_ids))
+            .map_err(|e| Error::NetworkOther(e.to_string()))
+    }
+}
+
 impl Into<WalletSnapshot> for Wallet {
     fn into(self) -> WalletSnapshot {
         WalletSnapshot {
@@ -240,7 +255,33 @@ mod test {
         Ok(())
     }
 
-    #[allow(unused)]
+    #[test]
+    fn canonical_bytes_are_independent_of_credit_order() -> Result<()> {
+        let recipient = get_random_pk();
+        let credits: Vec<_> = (0..10)
+            .map(|_| Credit {
+                id: get_random_xor(),
+                recipient,
+                amount: Money::from_nano(10),
+                msg: "asdf".to_string(),
+            })
+            .collect();
+        let mut wallet = Wallet::new(recipient);
+        let mut reversed = Wallet::new(recipient);
+        for credit in &credits {
+            wallet.apply_credit(credit.clone())?;
+        }
+        for credit in credits.iter().rev() {
+            reversed.apply_credit(credit.clone())?;
+        }
+
+        let snapshot: WalletSnapshot = wallet.into();
+        let reversed: WalletSnapshot = reversed.into();
+
+        assert_eq!(snapshot.canonical_bytes()?, reversed.canonical_bytes()?);
+        Ok(())
+    }
+
     fn get_random_xor() -> XorName {
         XorName::random()
     }
@@ -7,18 +7,117 @@
 // permissions and limitations relating to use of the SAFE Network Software.
 
 use super::{
+    consistency::check_consistency,
+    multisig::{verify_actor_shares, MultiSigCredit, MultiSigDebit},
+    payload::{
+        actor_credit_payload, actor_debit_payload, format_mismatch, signing_payload, PayloadFormat,
+    },
+    verify::{verify_credit_proof, verify_credit_proof_under, verify_transfer_proof},
     wallet::{Wallet, WalletSnapshot},
-    Outcome, TernaryResult,
+    BincodeCodec, Clock, Codec, KeyIndex, Outcome, SystemClock, TernaryResult,
 };
+use crdts::Dot;
 use log::debug;
-#[cfg(feature = "simulated-payouts")]
-use sn_data_types::Credit;
+use serde::Serialize;
 use sn_data_types::{
-    CreditAgreementProof, Debit, Error, KnownGroupAdded, Money, PublicKey, ReplicaEvent, Result,
-    SignedCredit, SignedDebit, TransferAgreementProof, TransferRegistered,
+    Credit, CreditAgreementProof, CreditId, Debit, Error, KnownGroupAdded, Money, PublicKey,
+    ReplicaEvent, Result, SignedCredit, SignedDebit, TransferAgreementProof, TransferRegistered,
 };
-use std::collections::HashSet;
-use threshold_crypto::{PublicKeySet, PublicKeyShare};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use threshold_crypto::{PublicKeySet, PublicKeyShare, SecretKey};
+
+/// The default number of validated debits kept for diagnostics.
+const DEFAULT_RECENT_PENDING_CAPACITY: usize = 16;
+
+/// A record of a validated debit, kept for diagnosing stalled counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingRecord {
+    /// The counter of the debit.
+    pub counter: u64,
+    /// The amount of the debit.
+    pub amount: Money,
+    /// When the validation was applied.
+    pub timestamp: SystemTime,
+}
+
+/// A summary of the state of a Replica, f.ex. for attaching to support tickets.
+/// It holds no secrets, and the wallet key is hex encoded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplicaDiagnostics {
+    /// The hex encoded key of the wallet.
+    pub id: String,
+    /// The balance of the wallet.
+    pub balance: Money,
+    /// The counter of the next debit to validate.
+    pub next_counter: u64,
+    /// The counter of the pending debit, if any.
+    pub pending_debit: Option<u64>,
+    /// The number of known groups of Replicas.
+    pub known_group_count: usize,
+    /// Whether the genesis credit has been applied.
+    pub genesised: bool,
+}
+
+/// The classification of a credit received at sync, before applying it.
+#[derive(Debug)]
+pub enum CreditClassification {
+    /// A validly signed credit, not yet applied.
+    New,
+    /// A credit that is already applied, or earlier in the same batch.
+    Duplicate,
+    /// A credit failing verification.
+    Invalid(Error),
+    /// A credit not classified before the deadline, to be rescheduled.
+    TimedOut,
+}
+
+/// How transfers to the burn address are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BurnPolicy {
+    /// Burns are allowed, and can be told apart with `WalletReplica::is_burn`.
+    Flag,
+    /// Burns are rejected at validation.
+    Reject,
+}
+
+/// Which signature verification failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyKind {
+    /// The actor signatures of a debit and credit.
+    ActorSignature,
+    /// The signatures of our peers on a registered transfer.
+    RegisteredProof,
+    /// The signatures of a known group on a propagated credit.
+    PropagatedProof,
+}
+
+/// The context of a failed signature verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyContext {
+    /// Which verification failed.
+    pub kind: VerifyKind,
+    /// The id of the wallet of the Replica.
+    pub wallet: PublicKey,
+}
+
+/// A callback invoked on every failed signature verification.
+pub type VerifyFailureHook = Arc<dyn Fn(&VerifyContext) + Send + Sync>;
+
+/// Holds the optional hook, so that the Replica can still derive Debug.
+#[derive(Clone, Default)]
+struct OnVerifyFailure(Option<VerifyFailureHook>);
+
+impl Debug for OnVerifyFailure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "OnVerifyFailure(Some(..))"),
+            None => write!(f, "OnVerifyFailure(None)"),
+        }
+    }
+}
 
 /// The Replica is the part of an AT2 system
 /// that forms validating groups, and signs
@@ -27,8 +126,8 @@ use threshold_crypto::{PublicKeySet, PublicKeyShare};
 /// apply operations that has a valid "debit agreement proof"
 /// from the group, i.e. signatures from a quorum of its peers.
 /// Replicas don't initiate transfers or drive the algo - only Actors do.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct WalletReplica {
+#[derive(Debug, Clone)]
+pub struct WalletReplica<C: Codec = BincodeCodec> {
     /// The public key of the Wallet.
     id: PublicKey,
     /// The public key share of this Replica.
@@ -37,6 +136,8 @@ pub struct WalletReplica {
     key_index: usize,
     /// The PK set of our peer Replicas.
     peer_replicas: PublicKeySet,
+    /// The combined key of our peer Replicas, kept since every verification uses it.
+    peer_key: PublicKey,
     /// PK sets of other known groups of Replicas.
     other_groups: HashSet<PublicKeySet>,
     /// All wallets that this Replica validates transfers for.
@@ -44,17 +145,108 @@ pub struct WalletReplica {
     /// Ensures that invidual wallet's debit
     /// initiations (ValidateTransfer cmd) are sequential.
     pending_debit: Option<u64>,
+    /// The registered debits and propagated credits
+    /// applied to this instance, in order of application.
+    history: Vec<ReplicaEvent>,
+    /// The debits in `history`, by counter, for detecting equivocation.
+    registered_debits: BTreeMap<u64, SignedDebit>,
+    /// The net amount applied to the wallet outside of `history`,
+    /// i.e. the balance at snapshot plus any simulated transfers.
+    history_base: i128,
+    /// The most recently validated debits, oldest first.
+    recent_pending: VecDeque<PendingRecord>,
+    /// The max number of records in `recent_pending`.
+    recent_pending_capacity: usize,
+    /// When the pending debit was validated.
+    pending_since: Option<SystemTime>,
+    /// How long a validated debit can be pending before it expires.
+    pending_debit_ttl: Option<Duration>,
+    /// The source of time for time-dependent logic.
+    clock: Arc<dyn Clock>,
+    /// The genesis credit, if applied to this instance.
+    genesis_proof: Option<CreditAgreementProof>,
+    /// The format of what the Replicas sign, that proofs are verified under.
+    format: PayloadFormat,
+    /// Whether propagated credits must be signed by our current peers' key.
+    strict_verification: bool,
+    /// The distinct recipients of the registered debits.
+    paid_recipients: HashSet<PublicKey>,
+    /// The encoding of what the Replicas sign.
+    codec: Arc<C>,
+    /// The designated burn address, and how transfers to it are handled.
+    burn_address: Option<(PublicKey, BurnPolicy)>,
+    /// The credits received via propagation, but not yet applied.
+    awaiting_application: BTreeSet<CreditId>,
+    /// Invoked on every failed signature verification.
+    on_verify_failure: OnVerifyFailure,
+    /// How many counters beyond the next one can be validated ahead of sequence.
+    lookahead: u64,
+    /// The debits validated ahead of sequence, by counter, until the
+    /// pending debit reaches them.
+    ahead_pending: BTreeMap<u64, Money>,
+    /// The amounts of the validated debits not yet registered, by counter.
+    pending_amounts: BTreeMap<u64, Money>,
+    /// The smallest amount of a propagated credit that is received.
+    dust_threshold: Money,
+}
+
+impl<C: Codec> PartialEq for WalletReplica<C> {
+    // The clock, codec and hook are not part of the state.
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.replica_id == other.replica_id
+            && self.key_index == other.key_index
+            && self.peer_replicas == other.peer_replicas
+            && self.other_groups == other.other_groups
+            && self.wallet == other.wallet
+            && self.pending_debit == other.pending_debit
+            && self.history == other.history
+            && self.registered_debits == other.registered_debits
+            && self.history_base == other.history_base
+            && self.recent_pending == other.recent_pending
+            && self.recent_pending_capacity == other.recent_pending_capacity
+            && self.pending_since == other.pending_since
+            && self.pending_debit_ttl == other.pending_debit_ttl
+            && self.genesis_proof == other.genesis_proof
+            && self.format == other.format
+            && self.strict_verification == other.strict_verification
+            && self.paid_recipients == other.paid_recipients
+            && self.burn_address == other.burn_address
+            && self.awaiting_application == other.awaiting_application
+            && self.lookahead == other.lookahead
+            && self.ahead_pending == other.ahead_pending
+            && self.pending_amounts == other.pending_amounts
+            && self.dust_threshold == other.dust_threshold
+    }
 }
 
+impl<C: Codec> Eq for WalletReplica<C> {}
+
 impl WalletReplica {
     /// A new Replica instance from a history of events.
     pub fn from_history(
         id: PublicKey,
         replica_id: PublicKeyShare,
-        key_index: usize,
+        key_index: KeyIndex,
         peer_replicas: PublicKeySet,
         events: Vec<ReplicaEvent>,
     ) -> Result<Self> {
+        let (instance, _) =
+            Self::from_history_counted(id, replica_id, key_index, peer_replicas, events)?;
+        Ok(instance)
+    }
+
+    /// A new Replica instance from a history of events, like `from_history`,
+    /// along with the number of events applied, f.ex. for reconciling with the input.
+    /// Events already applied, i.e. a re-delivered credit, or validated or registered debit,
+    /// change no state and are skipped, and not counted.
+    pub fn from_history_counted(
+        id: PublicKey,
+        replica_id: PublicKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+        events: Vec<ReplicaEvent>,
+    ) -> Result<(Self, usize)> {
         let mut instance = Self::from_snapshot(
             id,
             replica_id,
@@ -63,26 +255,106 @@ impl WalletReplica {
             Default::default(),
             Wallet::new(id),
             None,
+            Arc::new(SystemClock),
         );
 
+        let mut applied = 0;
         for e in events {
+            if instance.is_applied(&e) {
+                continue;
+            }
             instance.apply(e)?;
+            applied += 1;
         }
 
-        Ok(instance)
+        Ok((instance, applied))
+    }
+
+    /// A new Replica instance from a history of events from untrusted storage.
+    /// The signatures of every registered and propagated transfer are verified
+    /// against the trusted keys before it is applied, and replay aborts on the first failure.
+    pub fn from_history_verified(
+        id: PublicKey,
+        replica_id: PublicKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+        events: Vec<ReplicaEvent>,
+        trusted_keys: &[PublicKey],
+    ) -> Result<Self> {
+        for e in &events {
+            match e {
+                ReplicaEvent::TransferRegistered(e) => {
+                    verify_transfer_proof(&e.transfer_proof, trusted_keys)?
+                }
+                ReplicaEvent::TransferPropagated(e) => {
+                    verify_credit_proof(&e.credit_proof, trusted_keys)?
+                }
+                _ => (),
+            }
+        }
+        Self::from_history(id, replica_id, key_index, peer_replicas, events)
+    }
+
+    /// A new Replica instance from current state, rejecting a degenerate
+    /// key set of our peers, which can never sign a valid proof, i.e. one whose
+    /// combined key is the identity, or with duplicate key shares within a quorum,
+    /// or where our key share is not the one at our index.
+    /// The key set does not carry the size of the group, so a threshold
+    /// beyond it can't be told apart here.
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_from_snapshot(
+        id: PublicKey,
+        replica_id: PublicKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+        other_groups: HashSet<PublicKeySet>,
+        wallet: Wallet,
+        pending_debit: Option<u64>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        // The combined key of a set with a zero secret is the identity,
+        // which no quorum of shares can produce a signature for.
+        if peer_replicas.public_key() == SecretKey::default().public_key() {
+            return Err(Error::InvalidOperation);
+        }
+        // With a threshold of zero, every share is the combined key.
+        let quorum = peer_replicas.threshold() + 1;
+        if quorum > 1 {
+            let shares: HashSet<_> = (0..quorum)
+                .map(|index| peer_replicas.public_key_share(index))
+                .collect();
+            if shares.len() < quorum {
+                return Err(Error::InvalidOperation);
+            }
+        }
+        if peer_replicas.public_key_share(key_index.0) != replica_id {
+            return Err(Error::InvalidOperation);
+        }
+        Ok(Self::from_snapshot(
+            id,
+            replica_id,
+            key_index,
+            peer_replicas,
+            other_groups,
+            wallet,
+            pending_debit,
+            clock,
+        ))
     }
 
     /// A new Replica instance from current state.
+    #[allow(clippy::too_many_arguments)]
     pub fn from_snapshot(
         id: PublicKey,
         replica_id: PublicKeyShare,
-        key_index: usize,
+        key_index: KeyIndex,
         peer_replicas: PublicKeySet,
         other_groups: HashSet<PublicKeySet>,
         wallet: Wallet,
         pending_debit: Option<u64>,
+        clock: Arc<dyn Clock>,
     ) -> Self {
-        Self {
+        Self::from_snapshot_with_codec(
             id,
             replica_id,
             key_index,
@@ -90,6 +362,123 @@ impl WalletReplica {
             other_groups,
             wallet,
             pending_debit,
+            clock,
+            Arc::new(BincodeCodec),
+        )
+    }
+}
+
+impl<C: Codec> WalletReplica<C> {
+    /// A new Replica instance from current state, verifying values encoded with the codec.
+    /// Pass the same codec instance to the signing `ReplicaSigning`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_snapshot_with_codec(
+        id: PublicKey,
+        replica_id: PublicKeyShare,
+        key_index: KeyIndex,
+        peer_replicas: PublicKeySet,
+        other_groups: HashSet<PublicKeySet>,
+        wallet: Wallet,
+        pending_debit: Option<u64>,
+        clock: Arc<dyn Clock>,
+        codec: Arc<C>,
+    ) -> Self {
+        let history_base = wallet.balance().as_nano() as i128;
+        let peer_key = PublicKey::Bls(peer_replicas.public_key());
+        Self {
+            id,
+            replica_id,
+            key_index: key_index.0,
+            peer_replicas,
+            peer_key,
+            other_groups,
+            wallet,
+            pending_debit,
+            history: vec![],
+            registered_debits: Default::default(),
+            history_base,
+            recent_pending: VecDeque::new(),
+            recent_pending_capacity: DEFAULT_RECENT_PENDING_CAPACITY,
+            pending_since: None,
+            pending_debit_ttl: None,
+            clock,
+            genesis_proof: None,
+            format: PayloadFormat::default(),
+            strict_verification: false,
+            paid_recipients: Default::default(),
+            codec,
+            burn_address: None,
+            awaiting_application: Default::default(),
+            on_verify_failure: Default::default(),
+            lookahead: 0,
+            ahead_pending: Default::default(),
+            pending_amounts: Default::default(),
+            dust_threshold: Money::zero(),
+        }
+    }
+
+    /// Sets how many counters beyond the next one can be validated, so that a
+    /// client can pipeline debits. Defaults to 0, i.e. strictly sequential debits.
+    pub fn set_lookahead(&mut self, lookahead: u64) {
+        self.lookahead = lookahead;
+    }
+
+    /// Sets the smallest amount of a propagated credit that is received, so
+    /// that dust credits can't bloat the wallet. Defaults to zero, i.e. any amount.
+    /// This only applies to credits, not to debits.
+    pub fn set_dust_threshold(&mut self, dust_threshold: Money) {
+        self.dust_threshold = dust_threshold;
+    }
+
+    /// Sets the callback invoked on every failed signature verification,
+    /// f.ex. for alerting on possible attacks. None means no callback.
+    pub fn set_on_verify_failure(&mut self, hook: Option<VerifyFailureHook>) {
+        self.on_verify_failure = OnVerifyFailure(hook);
+    }
+
+    /// Sets the designated burn address, and how transfers to it are handled.
+    /// None means there is no burn address.
+    pub fn set_burn_address(&mut self, burn_address: Option<(PublicKey, BurnPolicy)>) {
+        self.burn_address = burn_address;
+    }
+
+    /// Sets whether propagated credits are only accepted when signed
+    /// by the current key of our peers, i.e. not by a past key or another known group.
+    pub fn set_strict_verification(&mut self, strict: bool) {
+        self.strict_verification = strict;
+    }
+
+    /// Sets the version of the wire format to verify proofs under.
+    /// Must be the version that the signing Replicas use.
+    pub fn set_wire_version(&mut self, wire_version: u8) {
+        self.format.wire_version = wire_version;
+    }
+
+    /// Sets the network that the proofs must be bound to, see `ReplicaSigning::set_network_id`.
+    /// With a network set, a proof that does not verify is reported as from the wrong network,
+    /// since one signed for another network can't be told apart from a forged one.
+    pub fn set_network_id(&mut self, network_id: Option<[u8; 32]>) {
+        self.format.network_id = network_id;
+    }
+
+    /// Sets the context that the proofs must be bound to, see `ReplicaSigning::set_context`.
+    /// With a context set, a proof that does not verify is reported as from the wrong context.
+    pub fn set_context(&mut self, context: Vec<u8>) {
+        self.format.context = context;
+    }
+
+    /// Sets how long a validated debit can be pending before
+    /// it expires, see `expire_pending_debit`. None never expires.
+    pub fn set_pending_debit_ttl(&mut self, ttl: Option<Duration>) {
+        self.pending_debit_ttl = ttl;
+    }
+
+    /// Sets the max number of validated debits kept for diagnostics.
+    /// Older records are dropped first.
+    pub fn set_recent_pending_capacity(&mut self, capacity: usize) {
+        self.recent_pending_capacity = capacity;
+        while self.recent_pending.len() > capacity {
+            let _ = self.recent_pending.pop_front();
         }
     }
 
@@ -102,12 +491,194 @@ impl WalletReplica {
         self.wallet.balance()
     }
 
+    /// How much more the wallet can receive before its balance overflows.
+    pub fn remaining_capacity(&self) -> Money {
+        Money::from_nano(u64::MAX - self.balance().as_nano())
+    }
+
+    /// Whether the genesis credit has been applied to the wallet,
+    /// i.e. the Replica is bootstrapped, regardless of the current balance.
+    pub fn is_genesised(&self) -> bool {
+        self.genesis_proof.is_some() || self.wallet.contains(&CreditId::default())
+    }
+
     ///
     pub fn wallet(&self) -> Option<WalletSnapshot> {
         let wallet = self.wallet.to_owned();
         Some(wallet.into())
     }
 
+    /// Whether the two Replicas hold the same money state of the same wallet,
+    /// i.e. id, balance, next debit and applied credits, f.ex. for reconciliation.
+    /// Local state such as known groups and key index is not compared.
+    pub fn balance_equivalent(&self, other: &Self) -> bool {
+        self.id == other.id && self.wallet == other.wallet
+    }
+
+    /// The index of this Replica key share, in the group set.
+    pub fn key_index(&self) -> usize {
+        self.key_index
+    }
+
+    /// The full wallet, f.ex. for persisting it and rebuilding
+    /// the Replica with `from_snapshot`.
+    pub fn clone_wallet(&self) -> Wallet {
+        self.wallet.clone()
+    }
+
+    /// The public keys of the other groups of Replicas that we know of.
+    pub fn known_group_keys(&self) -> impl Iterator<Item = PublicKey> + '_ {
+        self.other_groups
+            .iter()
+            .map(|set| PublicKey::Bls(set.public_key()))
+    }
+
+    /// Whether the registered transfer pays the burn address.
+    pub fn is_burn(&self, registered: &TransferRegistered) -> bool {
+        match self.burn_address {
+            Some((address, _)) => registered.transfer_proof.signed_credit.recipient() == address,
+            None => false,
+        }
+    }
+
+    /// The distinct recipients that this wallet has paid,
+    /// f.ex. for compliance reporting.
+    /// Only debits registered with this instance are known.
+    pub fn paid_recipients(&self) -> HashSet<PublicKey> {
+        self.paid_recipients.clone()
+    }
+
+    /// A summary of the state of this instance, see `ReplicaDiagnostics`.
+    pub fn diagnostics(&self) -> ReplicaDiagnostics {
+        let id = bincode::serialize(&self.id)
+            .unwrap_or_default()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        ReplicaDiagnostics {
+            id,
+            balance: self.balance(),
+            next_counter: self.next_expected_counter(),
+            pending_debit: self.pending_debit,
+            known_group_count: self.other_groups.len(),
+            genesised: self.is_genesised(),
+        }
+    }
+
+    /// The most recently validated debits, oldest first.
+    /// This is purely diagnostic, f.ex. for finding out how a counter got stuck.
+    pub fn recent_pending(&self) -> Vec<PendingRecord> {
+        self.recent_pending.iter().copied().collect()
+    }
+
+    /// The total amount of the validated debits not yet registered.
+    /// Only debits validated with this instance are known, so for an instance
+    /// created from a snapshot with a pending debit, its amount is not included.
+    pub fn pending_total(&self) -> Money {
+        let total = self
+            .pending_amounts
+            .values()
+            .try_fold(Money::zero(), |sum, amount| sum.checked_add(*amount));
+        total.unwrap_or_else(|| Money::from_nano(u64::MAX))
+    }
+
+    /// The balance less the pending debits, i.e. what is left to spend
+    /// once the debits validated so far are registered.
+    pub fn spendable_balance(&self) -> Money {
+        self.balance()
+            .checked_sub(self.pending_total())
+            .unwrap_or_else(Money::zero)
+    }
+
+    /// The counter of the last registered debit,
+    /// or None if no debit has been registered yet.
+    pub fn last_applied_counter(&self) -> Option<u64> {
+        self.wallet.next_debit().checked_sub(1)
+    }
+
+    /// The counter of the next debit to validate.
+    pub fn next_expected_counter(&self) -> u64 {
+        self.pending_debit.map_or(0, |counter| counter + 1)
+    }
+
+    /// The counters of the debits not yet validated, which debits validated
+    /// ahead of them under the look-ahead are waiting on, f.ex. for re-requesting them.
+    pub fn missing_counters(&self) -> Vec<u64> {
+        let next = self.next_expected_counter();
+        match self.ahead_pending.keys().next_back() {
+            Some(last) => (next..*last)
+                .filter(|counter| !self.ahead_pending.contains_key(counter))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Whether the debit with the given counter has been registered,
+    /// f.ex. for a client to avoid re-submitting a transfer that already landed.
+    pub fn is_counter_registered(&self, counter: u64) -> bool {
+        counter < self.wallet.next_debit()
+    }
+
+    /// The registered debits and propagated credits applied after
+    /// the debit with the given counter was registered, or all of them with None.
+    /// A client holding state up to `counter` can use this to catch up
+    /// without fetching the entire history.
+    /// Only events applied to this instance are known, so for an instance
+    /// created from a snapshot, history starts at the snapshot.
+    /// Returns None if the counter is not in the known history,
+    /// f.ex. when registered before the snapshot, or not yet registered.
+    pub fn events_since(&self, counter: Option<u64>) -> Option<Vec<ReplicaEvent>> {
+        let start = match counter {
+            None => 0,
+            Some(counter) => {
+                let position = self.history.iter().position(|event| match event {
+                    ReplicaEvent::TransferRegistered(e) => {
+                        e.transfer_proof.signed_debit.debit.id.counter == counter
+                    }
+                    _ => false,
+                })?;
+                position + 1
+            }
+        };
+        let events = self.history[start..]
+            .iter()
+            .filter(|event| {
+                matches!(
+                    event,
+                    ReplicaEvent::TransferRegistered(_) | ReplicaEvent::TransferPropagated(_)
+                )
+            })
+            .cloned()
+            .collect();
+        Some(events)
+    }
+
+    /// Recomputes the balance from the stored history, and
+    /// verifies that it equals the cached balance.
+    /// Used for integrity audits, f.ex. after a migration.
+    pub fn audit_balance(&self) -> Result<()> {
+        let derived = self
+            .history
+            .iter()
+            .fold(self.history_base, |sum, event| match event {
+                ReplicaEvent::TransferRegistered(e) => {
+                    sum - e.transfer_proof.signed_debit.amount().as_nano() as i128
+                }
+                ReplicaEvent::TransferPropagated(e) => {
+                    sum + e.credit_proof.amount().as_nano() as i128
+                }
+                _ => sum,
+            });
+        let cached = self.balance().as_nano() as i128;
+        if derived != cached {
+            return Err(Error::NetworkOther(format!(
+                "balance mismatch: cached {}, derived {}",
+                cached, derived
+            )));
+        }
+        Ok(())
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Cmds -------------------------------------
     /// -----------------------------------------------------------------
@@ -119,13 +690,85 @@ impl WalletReplica {
         credit_proof: &CreditAgreementProof,
         past_key: F,
     ) -> Outcome<()> {
+        // Replaying the applied genesis is benign, but there can be only one.
+        if let Some(applied) = &self.genesis_proof {
+            let identical = match (
+                self.codec.serialize(applied),
+                self.codec.serialize(credit_proof),
+            ) {
+                (Ok(applied), Ok(supplied)) => applied == supplied,
+                _ => return Err(Error::NetworkOther("Could not serialise genesis".into())),
+            };
+            return if identical {
+                Outcome::no_change()
+            } else {
+                Err(Error::InvalidOperation)
+            };
+        }
         // Genesis must be the first credit.
         if self.balance() != Money::zero() || self.pending_debit.is_some() {
             return Err(Error::InvalidOperation);
         }
+        // Genesis always has the canonical default credit id.
+        if credit_proof.id() != &CreditId::default() {
+            return Err(Error::InvalidOperation);
+        }
+        // Genesis is signed by the key set it embeds, i.e. our group, or the ghost keys
+        // of `get_genesis`, and not by any other group we know of.
+        let embedded = &credit_proof.debiting_replicas_keys;
+        if embedded != &self.peer_replicas {
+            let embedded_key = PublicKey::Bls(embedded.public_key());
+            if self.other_groups.contains(embedded)
+                || verify_credit_proof_under(
+                    self.codec.as_ref(),
+                    credit_proof,
+                    &[embedded_key],
+                    &self.format,
+                )
+                .is_err()
+            {
+                return Err(Error::InvalidOperation);
+            }
+        }
         self.receive_propagated(credit_proof, past_key)
     }
 
+    /// The unsigned transfer, i.e. the debit at the next counter of the wallet,
+    /// and its credit to the recipient, for a client to sign, see `actor_payloads`.
+    pub fn next_transfer_template(
+        &self,
+        recipient: PublicKey,
+        amount: Money,
+        msg: String,
+    ) -> Result<(Debit, Credit)> {
+        let debit = Debit {
+            id: Dot::new(self.id, self.next_expected_counter()),
+            amount,
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient,
+            amount,
+            msg,
+        };
+        Ok((debit, credit))
+    }
+
+    /// The unsigned transfer of the entire spendable balance, like `next_transfer_template`,
+    /// f.ex. for closing the wallet.
+    /// Errors if there is nothing to spend.
+    pub fn sweep_transfer_template(
+        &self,
+        recipient: PublicKey,
+        msg: String,
+    ) -> Result<(Debit, Credit)> {
+        let amount = self.spendable_balance();
+        if amount == Money::zero() {
+            return Err(Error::InsufficientBalance);
+        }
+        self.next_transfer_template(recipient, amount, msg)
+    }
+
     /// Adds a PK set for a a new group that we learn of.
     pub fn add_known_group(&self, group: PublicKeySet) -> Outcome<KnownGroupAdded> {
         if self.other_groups.contains(&group) {
@@ -134,67 +777,175 @@ impl WalletReplica {
         Outcome::success(KnownGroupAdded { group })
     }
 
+    /// Adds the PK sets of all the groups that we learn of at once, f.ex. when joining.
+    /// Groups already known, or repeated in the batch, are skipped.
+    /// Returns the ones actually added, or no change if there were none.
+    pub fn add_known_groups(&self, groups: Vec<PublicKeySet>) -> Outcome<Vec<KnownGroupAdded>> {
+        let mut seen = HashSet::new();
+        let added: Vec<_> = groups
+            .into_iter()
+            .filter(|group| !self.other_groups.contains(group) && seen.insert(group.clone()))
+            .map(|group| KnownGroupAdded { group })
+            .collect();
+        if added.is_empty() {
+            Outcome::no_change()
+        } else {
+            Outcome::success(added)
+        }
+    }
+
     /// For now, with test money there is no from wallet.., money is created from thin air.
     pub fn test_validate_transfer(
         &self,
         signed_debit: &SignedDebit,
         signed_credit: &SignedCredit,
     ) -> Outcome<()> {
-        if signed_debit.sender() == signed_credit.recipient() {
-            Err(Error::from("Sender and recipient are the same."))
-        } else if signed_credit.id() != &signed_debit.credit_id()? {
-            Err(Error::from("The credit does not correspond to the debit."))
-        } else if signed_credit.amount() != signed_debit.amount() {
-            Err(Error::from("Amounts must be equal."))
-        } else {
-            Outcome::success(())
-        }
+        check_consistency(&signed_debit.debit, &signed_credit.credit)?;
+        Outcome::success(())
     }
 
     /// Step 1. Main business logic validation of a debit.
+    /// The debit is checked against the spendable balance, i.e. the debits
+    /// validated but not yet registered are reserved from the balance.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                wallet = ?self.id,
+                counter = signed_debit.debit.id.counter,
+                amount = signed_debit.amount().as_nano()
+            )
+        )
+    )]
     pub fn validate(
         &self,
         signed_debit: &SignedDebit,
         signed_credit: &SignedCredit,
     ) -> Outcome<()> {
-        let debit = &signed_debit.debit;
-        let credit = &signed_credit.credit;
+        let outcome = self.validate_with_state(
+            signed_debit,
+            signed_credit,
+            self.pending_debit,
+            self.spendable_balance(),
+        );
+        if let Some(error) = outcome.rejection_reason() {
+            debug!("Rejected debit {:?}: {:?}", signed_debit.id(), error);
+        }
+        outcome
+    }
+
+    /// Validation of a debit, like `validate`, but against pending state kept
+    /// by the caller instead of by this instance, given as the counter and amount
+    /// of the pending debit. The amount is reserved from the balance.
+    /// With None, there is no pending debit, and the next counter
+    /// is the one after the last registered debit.
+    pub fn validate_with_pending(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        pending: Option<(u64, Money)>,
+    ) -> Outcome<()> {
+        let (pending_debit, balance) = match pending {
+            Some((counter, amount)) => (
+                Some(counter),
+                self.balance()
+                    .checked_sub(amount)
+                    .unwrap_or_else(Money::zero),
+            ),
+            None => (self.last_applied_counter(), self.balance()),
+        };
+        self.validate_with_state(signed_debit, signed_credit, pending_debit, balance)
+    }
 
+    /// Pre-check of a debit, before its credit is known, f.ex. for a reservation.
+    /// Runs the validations of `validate` that don't need the credit, i.e.
+    /// signature, sender, order and balance.
+    pub fn precheck_debit(&self, signed_debit: &SignedDebit) -> Outcome<()> {
         // Always verify signature first! (as to not leak any information).
-        if self
-            .verify_actor_signature(&signed_debit, &signed_credit)
+        let debit_bytes = actor_debit_payload(&signed_debit.debit)?;
+        if signed_debit
+            .sender()
+            .verify(&signed_debit.actor_signature, debit_bytes)
             .is_err()
         {
             return Outcome::rejected(Error::InvalidSignature);
-        } else if debit.sender() == credit.recipient() {
-            return Outcome::rejected(Error::from("Sender and recipient are the same."));
-        } else if credit.id() != &debit.credit_id()? {
-            return Outcome::rejected(Error::from("The credit does not correspond to the debit."));
-        } else if credit.amount() != debit.amount() {
-            return Outcome::rejected(Error::from("Amounts must be equal."));
-        } else if debit.amount() == Money::zero() {
-            return Outcome::rejected(Error::Unexpected(
-                "Transfer amount must be more than zero.".to_string(),
-            ));
-        } else if self.wallet.id() != debit.sender() {
-            return Outcome::rejected(Error::NoSuchSender);
-        } else if self.pending_debit.is_none() && debit.id.counter != 0 {
-            return Outcome::rejected(Error::from("out of order msg, actor's counter should be 0"));
-        } else if let Some(counter) = self.pending_debit {
-            if debit.id.counter != (counter + 1) {
-                return Outcome::rejected(Error::from(format!(
-                    "out of order msg, debit counter: {:?}, current counter: {:?}",
-                    debit.id.counter, counter
-                )));
-            }
-        } else if debit.amount() > self.balance() {
-            return Outcome::rejected(Error::InsufficientBalance);
         }
+        self.check_debit(
+            &signed_debit.debit,
+            self.pending_debit,
+            self.spendable_balance(),
+        )
+    }
 
-        Outcome::success(())
+    /// Validation of a debit, like `validate`, returning the id
+    /// of the credit that the validation checked against the debit.
+    pub fn validate_with_credit_id(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+    ) -> Outcome<CreditId> {
+        match self.validate(signed_debit, signed_credit)? {
+            Some(()) => Outcome::success(*signed_credit.id()),
+            None => Outcome::no_change(),
+        }
+    }
+
+    /// Validation of a debit from a wallet controlled by a group of actors,
+    /// which must be signed by at least `threshold` of the `actors`.
+    /// The wallet is keyed by the public key of the actor set.
+    pub fn validate_multisig(
+        &self,
+        debit: &MultiSigDebit,
+        credit: &MultiSigCredit,
+        actors: &PublicKeySet,
+        threshold: usize,
+    ) -> Outcome<()> {
+        // Always verify signature first! (as to not leak any information).
+        let signed = verify_actor_shares(&debit.debit, &debit.actor_signatures, actors, threshold)
+            .and_then(|()| {
+                verify_actor_shares(&credit.credit, &credit.actor_signatures, actors, threshold)
+            });
+        if signed.is_err()
+            || debit.debit.sender() != PublicKey::Bls(actors.public_key())
+            || debit.debit.sender() == credit.credit.recipient()
+        {
+            return Outcome::rejected(Error::InvalidSignature);
+        }
+        self.validate_debit(
+            &debit.debit,
+            &credit.credit,
+            self.pending_debit,
+            self.spendable_balance(),
+        )
+    }
+
+    /// Runs the validation of a debit against the state in the given snapshot,
+    /// instead of the live state, f.ex. for replaying disputes.
+    /// Nothing is mutated.
+    pub fn validate_against(
+        &self,
+        snapshot: &WalletSnapshot,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+    ) -> Outcome<()> {
+        // The last debit of the snapshot is the one preceding its next debit.
+        let last_debit = snapshot.debit_version.checked_sub(1);
+        self.validate_with_state(signed_debit, signed_credit, last_debit, snapshot.balance)
     }
 
     /// Step 2. Validation of agreement, and order at debit source.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                wallet = ?self.id,
+                counter = transfer_proof.signed_debit.debit.id.counter,
+                amount = transfer_proof.signed_debit.amount().as_nano()
+            )
+        )
+    )]
     pub fn register<F: FnOnce() -> Result<PublicKey>>(
         &self,
         transfer_proof: &TransferAgreementProof,
@@ -203,13 +954,24 @@ impl WalletReplica {
         debug!("Checking registered transfer");
 
         // Always verify signature first! (as to not leak any information).
-        if self
-            .verify_registered_proof(transfer_proof, past_key)
-            .is_err()
-        {
-            return Err(Error::InvalidSignature);
+        let mut keys = vec![self.peer_key];
+        let verified = self.verify_registered_proof(transfer_proof, || {
+            let key = past_key()?;
+            keys.push(key);
+            Ok(key)
+        });
+        if verified.is_err() {
+            let mismatch = format_mismatch(
+                self.codec.as_ref(),
+                &keys,
+                &transfer_proof.debit_sig,
+                &transfer_proof.signed_debit,
+                &self.format,
+            );
+            return Err(mismatch.unwrap_or_else(|| self.signature_error()));
         }
 
+        self.check_equivocation(&transfer_proof.signed_debit)?;
         let debit = &transfer_proof.signed_debit.debit;
         if self.wallet.next_debit() == debit.id().counter {
             Outcome::success(TransferRegistered {
@@ -222,6 +984,13 @@ impl WalletReplica {
 
     /// Step 3. Validation of TransferAgreementProof, and credit idempotency at credit destination.
     /// (Since this leads to a credit, there is no requirement on order.)
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(wallet = ?self.id, amount = credit_proof.amount().as_nano())
+        )
+    )]
     pub fn receive_propagated<F: FnOnce() -> Result<PublicKey>>(
         &self,
         credit_proof: &CreditAgreementProof,
@@ -229,13 +998,82 @@ impl WalletReplica {
     ) -> Outcome<()> {
         // Always verify signature first! (as to not leak any information).
         self.verify_propagated_proof(credit_proof, past_key)?;
-        if self.wallet.contains(&credit_proof.id()) {
+        if credit_proof.recipient() != self.id {
+            Outcome::rejected(Error::NetworkOther("credit recipient mismatch".into()))
+        } else if self.wallet.contains(&credit_proof.id()) {
             Outcome::no_change()
+        } else if credit_proof.amount() < self.dust_threshold {
+            Outcome::rejected(Error::NetworkOther("below dust threshold".into()))
+        } else if credit_proof.amount() > self.remaining_capacity() {
+            Outcome::rejected(Error::ExcessiveValue)
         } else {
             Outcome::success(())
         }
     }
 
+    /// Receives a propagated credit, like `receive_propagated`, and keeps
+    /// track of it as awaiting application, until its TransferPropagated is applied.
+    pub fn receive_propagated_awaiting<F: FnOnce() -> Result<PublicKey>>(
+        &mut self,
+        credit_proof: &CreditAgreementProof,
+        past_key: F,
+    ) -> Outcome<()> {
+        let result = self.receive_propagated(credit_proof, past_key)?;
+        if result.is_some() {
+            let _ = self.awaiting_application.insert(*credit_proof.id());
+        }
+        Ok(result)
+    }
+
+    /// The ids of the credits received via propagation, but not yet applied.
+    pub fn awaiting_application(&self) -> Vec<CreditId> {
+        self.awaiting_application.iter().copied().collect()
+    }
+
+    /// The key set embedded in the proof, when it is signed by that set,
+    /// but neither by our group nor by any known group, f.ex. for
+    /// prompting an operator to add the group, instead of only rejecting the proof.
+    pub fn identify_unknown_signer(&self, proof: &CreditAgreementProof) -> Option<PublicKeySet> {
+        let verify = |keys: &[PublicKey]| {
+            verify_credit_proof_under(self.codec.as_ref(), proof, keys, &self.format).is_ok()
+        };
+        let mut known = vec![self.peer_key];
+        known.extend(self.known_group_keys());
+        let embedded = PublicKey::Bls(proof.debiting_replicas_keys.public_key());
+        if !verify(&known) && verify(&[embedded]) {
+            Some(proof.debiting_replicas_keys.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Classifies each of a batch of credits received at sync, in order,
+    /// so that the caller can apply only the new ones. Nothing is mutated.
+    /// The credits left when the deadline has passed are marked `TimedOut`,
+    /// though the first one is always classified, so that a rescheduled batch makes progress.
+    pub fn classify_credits<F: Fn() -> Result<PublicKey>>(
+        &self,
+        proofs: &[CreditAgreementProof],
+        past_key: F,
+        deadline: Instant,
+    ) -> Vec<CreditClassification> {
+        let mut seen = HashSet::new();
+        proofs
+            .iter()
+            .enumerate()
+            .map(|(i, proof)| {
+                if i > 0 && Instant::now() >= deadline {
+                    return CreditClassification::TimedOut;
+                }
+                match self.receive_propagated(proof, &past_key) {
+                    Ok(Some(())) if seen.insert(*proof.id()) => CreditClassification::New,
+                    Ok(_) => CreditClassification::Duplicate,
+                    Err(error) => CreditClassification::Invalid(error),
+                }
+            })
+            .collect()
+    }
+
     /// -----------------------------------------------------------------
     /// ---------------------- Mutation ---------------------------------
     /// -----------------------------------------------------------------
@@ -252,72 +1090,463 @@ impl WalletReplica {
             }
             ReplicaEvent::TransferValidated(e) => {
                 let debit = e.signed_debit.debit;
-                self.pending_debit = Some(debit.id.counter);
+                let now = self.clock.now();
+                let next = self.pending_debit.map_or(0, |counter| counter + 1);
+                // A debit behind the pending one is already pending or registered.
+                if debit.id.counter < next {
+                    return Ok(());
+                }
+                if debit.id.counter == next {
+                    let mut counter = next;
+                    // Catches up with any debits validated ahead of it.
+                    while self.ahead_pending.remove(&(counter + 1)).is_some() {
+                        counter += 1;
+                    }
+                    self.pending_debit = Some(counter);
+                } else {
+                    let _ = self.ahead_pending.insert(debit.id.counter, debit.amount());
+                }
+                let _ = self
+                    .pending_amounts
+                    .insert(debit.id.counter, debit.amount());
+                self.pending_since = Some(now);
+                self.record_pending(PendingRecord {
+                    counter: debit.id.counter,
+                    amount: debit.amount(),
+                    timestamp: now,
+                });
                 Ok(())
             }
             ReplicaEvent::TransferRegistered(e) => {
-                let debit = e.transfer_proof.signed_debit.debit;
+                self.check_equivocation(&e.transfer_proof.signed_debit)?;
+                let debit = &e.transfer_proof.signed_debit.debit;
                 self.wallet.apply_debit(Debit {
                     id: debit.id(),
                     amount: debit.amount(),
-                })
+                })?;
+                // Debits below the next counter can no longer be registered.
+                let next_debit = self.wallet.next_debit();
+                self.pending_amounts = self.pending_amounts.split_off(&next_debit);
+                self.ahead_pending = self.ahead_pending.split_off(&next_debit);
+                let _ = self
+                    .paid_recipients
+                    .insert(e.transfer_proof.signed_credit.recipient());
+                let _ = self.registered_debits.insert(
+                    e.transfer_proof.signed_debit.debit.id.counter,
+                    e.transfer_proof.signed_debit.clone(),
+                );
+                self.history.push(ReplicaEvent::TransferRegistered(e));
+                Ok(())
             }
             ReplicaEvent::TransferPropagated(e) => {
-                let credit = e.credit_proof.signed_credit.credit;
-                self.wallet.apply_credit(credit)
+                let credit = e.credit_proof.signed_credit.credit.clone();
+                if e.credit_proof.amount() > self.remaining_capacity() {
+                    return Err(Error::ExcessiveValue);
+                }
+                self.wallet.apply_credit(credit)?;
+                let _ = self.awaiting_application.remove(e.credit_proof.id());
+                if e.credit_proof.id() == &CreditId::default() {
+                    self.genesis_proof = Some(e.credit_proof.clone());
+                }
+                self.history.push(ReplicaEvent::TransferPropagated(e));
+                Ok(())
             }
         }
     }
 
-    /// Test-helper API to simulate Client CREDIT Transfers.
-    #[cfg(feature = "simulated-payouts")]
-    pub fn credit_without_proof(&mut self, credit: Credit) -> Result<()> {
-        self.wallet.simulated_credit(credit)
+    /// Mutation of state, applying a batch of credit proofs received at sync.
+    /// Credits already applied, or earlier in the batch, are skipped.
+    /// Every new proof must be to this wallet, and signed by our group or a known group.
+    /// If any of them is not, or the new credits together would overflow the balance,
+    /// none of them is applied.
+    /// As there is no propagation event of these credits, they are applied outside of `history`.
+    /// Returns the number of credits applied.
+    pub fn apply_credits(&mut self, proofs: Vec<CreditAgreementProof>) -> Result<usize> {
+        let mut seen = HashSet::new();
+        let new: Vec<_> = proofs
+            .into_iter()
+            .filter(|proof| !self.wallet.contains(proof.id()) && seen.insert(*proof.id()))
+            .collect();
+        let mut trusted_keys = vec![self.peer_key];
+        trusted_keys.extend(self.known_group_keys());
+        for proof in &new {
+            if proof.recipient() != self.id {
+                return Err(Error::NetworkOther("credit recipient mismatch".into()));
+            }
+            verify_credit_proof_under(self.codec.as_ref(), proof, &trusted_keys, &self.format)?;
+        }
+        let total = new
+            .iter()
+            .try_fold(0u64, |sum, proof| sum.checked_add(proof.amount().as_nano()));
+        match total {
+            Some(total) if Money::from_nano(total) <= self.remaining_capacity() => (),
+            _ => return Err(Error::ExcessiveValue),
+        }
+        let count = new.len();
+        let mut next = self.clone();
+        for proof in new {
+            next.wallet
+                .apply_credit(proof.signed_credit.credit.clone())?;
+            next.history_base += proof.amount().as_nano() as i128;
+            let _ = next.awaiting_application.remove(proof.id());
+            if proof.id() == &CreditId::default() {
+                next.genesis_proof = Some(proof);
+            }
+        }
+        *self = next;
+        Ok(count)
     }
 
-    /// Test-helper API to simulate Client DEBIT Transfers.
+    /// Applies the event, like `apply`, and returns the
+    /// balance before and after, f.ex. for notifying subscribers of a change.
+    /// Events not affecting the balance return it twice.
+    pub fn apply_with_balance_delta(&mut self, event: ReplicaEvent) -> Result<(Money, Money)> {
+        let old_balance = self.balance();
+        self.apply(event)?;
+        Ok((old_balance, self.balance()))
+    }
+
+    /// Applies the event, like `apply`, but tells whether it changed anything.
+    /// A known group that was already present is a harmless no-op, f.ex.
+    /// when replaying, and reports `false`.
+    pub fn apply_idempotent(&mut self, event: ReplicaEvent) -> Result<bool> {
+        if let ReplicaEvent::KnownGroupAdded(e) = &event {
+            if self.other_groups.contains(&e.group) {
+                return Ok(false);
+            }
+        }
+        self.apply(event)?;
+        Ok(true)
+    }
+
+    /// Applies all the events, or none of them.
+    /// If any event fails to apply, the state is rolled back
+    /// to what it was before the batch, and the error is returned.
+    pub fn apply_all(&mut self, events: Vec<ReplicaEvent>) -> Result<()> {
+        let mut next = self.clone();
+        for e in events {
+            next.apply(e)?;
+        }
+        *self = next;
+        Ok(())
+    }
+
+    /// Reverts a pending debit that was validated longer than the ttl ago,
+    /// and never registered, so that its counter can be validated again.
+    /// Returns whether the pending debit expired.
+    pub fn expire_pending_debit(&mut self) -> bool {
+        let (counter, since, ttl) = match (
+            self.pending_debit,
+            self.pending_since,
+            self.pending_debit_ttl,
+        ) {
+            (Some(counter), Some(since), Some(ttl)) => (counter, since, ttl),
+            _ => return false,
+        };
+        // A registered debit has nothing to revert.
+        if self.wallet.next_debit() > counter {
+            return false;
+        }
+        match self.clock.now().duration_since(since) {
+            Ok(elapsed) if elapsed >= ttl => {
+                // The debits it caught up with, and any validated ahead of it,
+                // were pending along with it, and expire as well.
+                while self
+                    .pending_debit
+                    .map_or(false, |counter| counter >= self.wallet.next_debit())
+                {
+                    self.revert_pending();
+                }
+                self.ahead_pending.clear();
+                self.pending_amounts.clear();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cancels the pending debit, f.ex. on request of the client, if it was never
+    /// registered, so that its amount is released and its counter can be validated again.
+    /// Returns whether a pending debit was cancelled.
+    pub fn cancel_pending_debit(&mut self) -> bool {
+        match self.pending_debit {
+            Some(counter) if counter >= self.wallet.next_debit() => {
+                self.revert_pending();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Rolls the wallet back to the snapshot, f.ex. a known-good one after
+    /// a detected corruption, to then replay events from there.
+    /// The snapshot and pending debit must be consistent, see `check_invariants`.
+    /// State derived from the applied events is reset, as when built `from_snapshot`.
+    pub fn restore_to(
+        &mut self,
+        snapshot: WalletSnapshot,
+        pending_debit: Option<u64>,
+    ) -> Result<()> {
+        Self::check_invariants(&snapshot, pending_debit)?;
+        self.wallet = Wallet::from(
+            self.id,
+            snapshot.balance,
+            snapshot.debit_version,
+            snapshot.credit_ids,
+        );
+        self.pending_debit = pending_debit;
+        self.pending_since = None;
+        self.ahead_pending.clear();
+        self.pending_amounts.clear();
+        self.history.clear();
+        self.registered_debits.clear();
+        self.history_base = self.wallet.balance().as_nano() as i128;
+        self.recent_pending.clear();
+        self.paid_recipients.clear();
+        self.awaiting_application.clear();
+        self.genesis_proof = None;
+        Ok(())
+    }
+
+    /// Test-helper API to simulate Client CREDIT Transfers, see `SimulatedReplica`.
+    #[cfg(feature = "simulated-payouts")]
+    pub(crate) fn credit_without_proof(&mut self, credit: Credit) -> Result<()> {
+        let amount = credit.amount();
+        self.wallet.simulated_credit(credit)?;
+        self.history_base += amount.as_nano() as i128;
+        Ok(())
+    }
+
+    /// Test-helper API to simulate Client DEBIT Transfers, see `SimulatedReplica`.
     #[cfg(feature = "simulated-payouts")]
-    pub fn debit_without_proof(&mut self, debit: Debit) -> Result<()> {
-        self.wallet.simulated_debit(debit)
+    pub(crate) fn debit_without_proof(&mut self, debit: Debit) -> Result<()> {
+        let amount = debit.amount();
+        self.wallet.simulated_debit(debit)?;
+        self.history_base -= amount.as_nano() as i128;
+        Ok(())
     }
 
     /// -----------------------------------------------------------------
     /// ---------------------- Private methods --------------------------
     /// -----------------------------------------------------------------
 
-    ///
+    /// Reports a debit conflicting with the one already registered at its counter,
+    /// i.e. an equivocation by the actor, with both debits as evidence.
+    /// Only debits registered with this instance are known.
+    fn check_equivocation(&self, signed_debit: &SignedDebit) -> Result<()> {
+        let counter = signed_debit.debit.id.counter;
+        match self.registered_debit(counter) {
+            Some(registered) if registered != signed_debit => Err(Error::NetworkOther(format!(
+                "debit equivocation at counter {}: registered {:?}, conflicting {:?}",
+                counter, registered, signed_debit
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// The debit registered with this instance at the counter, if any.
+    fn registered_debit(&self, counter: u64) -> Option<&SignedDebit> {
+        self.registered_debits.get(&counter)
+    }
+
+    /// Whether the event was already applied, i.e. applying it again would change no state.
+    fn is_applied(&self, event: &ReplicaEvent) -> bool {
+        match event {
+            ReplicaEvent::TransferRegistered(e) => {
+                let signed_debit = &e.transfer_proof.signed_debit;
+                self.registered_debit(signed_debit.debit.id.counter) == Some(signed_debit)
+            }
+            ReplicaEvent::TransferValidated(e) => {
+                e.signed_debit.debit.id.counter < self.next_expected_counter()
+            }
+            ReplicaEvent::TransferPropagated(e) => self.wallet.contains(&e.credit_proof.id()),
+            _ => false,
+        }
+    }
+
+    /// Checks the consistency of the state, f.ex. after each event of a replay.
+    #[cfg(feature = "testing")]
+    pub(crate) fn check_state_invariants(&self) -> Result<()> {
+        Self::check_invariants(&self.wallet.clone().into(), self.pending_debit)?;
+        self.audit_balance()
+    }
+
+    /// Checks that the pending debit is consistent with the snapshot,
+    /// i.e. that every registered debit was validated first.
+    fn check_invariants(snapshot: &WalletSnapshot, pending_debit: Option<u64>) -> Result<()> {
+        let registered = snapshot.debit_version;
+        let consistent = match pending_debit {
+            None => registered == 0,
+            Some(counter) => counter + 1 >= registered,
+        };
+        if consistent {
+            Ok(())
+        } else {
+            Err(Error::NetworkOther(format!(
+                "inconsistent snapshot: {} debits registered, pending debit {:?}",
+                registered, pending_debit
+            )))
+        }
+    }
+
+    /// Sets the pending debit back to the one before it,
+    /// or to none if the reverted debit was the first.
+    fn revert_pending(&mut self) {
+        if let Some(counter) = self.pending_debit {
+            let _ = self.pending_amounts.remove(&counter);
+        }
+        self.pending_debit = self
+            .pending_debit
+            .and_then(|counter| counter.checked_sub(1));
+        self.pending_since = None;
+    }
+
+    /// Keeps the record, dropping the oldest when at capacity.
+    fn record_pending(&mut self, record: PendingRecord) {
+        if self.recent_pending_capacity == 0 {
+            return;
+        }
+        while self.recent_pending.len() >= self.recent_pending_capacity {
+            let _ = self.recent_pending.pop_front();
+        }
+        self.recent_pending.push_back(record);
+    }
+
+    /// Validation of a debit, given the counter of the preceding debit
+    /// and the balance to debit from.
+    fn validate_with_state(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        pending_debit: Option<u64>,
+        balance: Money,
+    ) -> Outcome<()> {
+        // Always verify signature first! (as to not leak any information).
+        if self
+            .verify_actor_signature(signed_debit, signed_credit)
+            .is_err()
+        {
+            return Outcome::rejected(Error::InvalidSignature);
+        }
+        self.validate_debit(
+            &signed_debit.debit,
+            &signed_credit.credit,
+            pending_debit,
+            balance,
+        )
+    }
+
+    /// The validation of an already signature verified debit, against the given state.
+    fn validate_debit(
+        &self,
+        debit: &Debit,
+        credit: &Credit,
+        pending_debit: Option<u64>,
+        balance: Money,
+    ) -> Outcome<()> {
+        if let Some((address, BurnPolicy::Reject)) = self.burn_address {
+            if credit.recipient() == address {
+                return Outcome::rejected(Error::from(
+                    "Transfers to the burn address are not allowed.",
+                ));
+            }
+        }
+        check_consistency(debit, credit)?;
+        self.check_debit(debit, pending_debit, balance)
+    }
+
+    /// The validation of the debit that doesn't need its credit, against the given state.
+    fn check_debit(
+        &self,
+        debit: &Debit,
+        pending_debit: Option<u64>,
+        balance: Money,
+    ) -> Outcome<()> {
+        if debit.amount() == Money::zero() {
+            return Outcome::rejected(Error::Unexpected(
+                "Transfer amount must be more than zero.".to_string(),
+            ));
+        } else if self.wallet.id() != debit.sender() {
+            // Not unknown, just not ours; the caller can forward it to the right replica.
+            return Outcome::rejected(Error::NetworkOther(format!(
+                "Wrong replica: debit sender {:?} is not the wallet {:?} of this replica.",
+                debit.sender(),
+                self.wallet.id()
+            )));
+        }
+        let next = pending_debit.map_or(0, |counter| counter + 1);
+        let counter = debit.id.counter;
+        let within_lookahead = counter >= next && counter - next <= self.lookahead;
+        if !within_lookahead || self.ahead_pending.contains_key(&counter) {
+            return Outcome::rejected(match pending_debit {
+                None if self.lookahead == 0 => {
+                    Error::from("out of order msg, actor's counter should be 0")
+                }
+                None => Error::from(format!(
+                    "out of order msg, debit counter: {:?}, no current counter",
+                    counter
+                )),
+                Some(current) => Error::from(format!(
+                    "out of order msg, debit counter: {:?}, current counter: {:?}",
+                    counter, current
+                )),
+            });
+        }
+
+        // Checked regardless of the debit order.
+        if debit.amount() > balance {
+            return Outcome::rejected(Error::InsufficientBalance);
+        }
+
+        Outcome::success(())
+    }
+
+    /// Verifies the actor signatures with the key of the sender,
+    /// whichever its type, i.e. both client (ed25519) and node (BLS) keys.
     fn verify_actor_signature(
         &self,
         signed_debit: &SignedDebit,
         signed_credit: &SignedCredit,
     ) -> Result<()> {
-        println!("Actor signature verification");
+        debug!("Actor signature verification");
+        let debit_bytes = actor_debit_payload(&signed_debit.debit)?;
+        let credit_bytes = actor_credit_payload(&signed_credit.credit)?;
+        self.verify_actor_signature_bytes(signed_debit, signed_credit, &debit_bytes, &credit_bytes)
+    }
+
+    /// Verifies the actor signatures over already serialised
+    /// debit and credit bytes, avoiding a redundant serialisation.
+    /// The bytes must be the serialised `signed_debit.debit` and `signed_credit.credit`.
+    fn verify_actor_signature_bytes(
+        &self,
+        signed_debit: &SignedDebit,
+        signed_credit: &SignedCredit,
+        debit_bytes: &[u8],
+        credit_bytes: &[u8],
+    ) -> Result<()> {
         let debit = &signed_debit.debit;
         let credit = &signed_credit.credit;
-        let debit_bytes = match bincode::serialize(&debit) {
-            Err(_) => return Err(Error::NetworkOther("Could not serialise debit".into())),
-            Ok(bytes) => bytes,
-        };
-        let credit_bytes = match bincode::serialize(&credit) {
-            Err(_) => return Err(Error::NetworkOther("Could not serialise credit".into())),
-            Ok(bytes) => bytes,
-        };
+        // A self-transfer is never validly signed.
+        if debit.sender() == credit.recipient() {
+            return Err(Error::from("Sender and recipient are the same."));
+        }
 
         let valid_debit = signed_debit
             .sender()
             .verify(&signed_debit.actor_signature, debit_bytes)
             .is_ok();
 
-        println!("Debit is valid?: {:?}", valid_debit);
+        debug!("Debit is valid?: {:?}", valid_debit);
         let valid_credit = signed_debit
             .sender()
             .verify(&signed_credit.actor_signature, credit_bytes)
             .is_ok();
-        println!("Credit is valid?: {:?}", valid_debit);
+        debug!("Credit is valid?: {:?}", valid_credit);
 
         if valid_debit && valid_credit && credit.id() == &debit.credit_id()? {
             Ok(())
         } else {
+            self.report_verify_failure(VerifyKind::ActorSignature);
             Err(Error::InvalidSignature)
         }
     }
@@ -335,16 +1564,18 @@ impl WalletReplica {
             ));
         }
         // Check that the proof corresponds to a public key set of our peers.
-        let debit_bytes = match bincode::serialize(&proof.signed_debit) {
-            Ok(bytes) => bytes,
-            Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
-        };
-        let credit_bytes = match bincode::serialize(&proof.signed_credit) {
-            Ok(bytes) => bytes,
-            Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
-        };
+        let debit_bytes =
+            match signing_payload(self.codec.as_ref(), &self.format, &proof.signed_debit) {
+                Ok(bytes) => bytes,
+                Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
+            };
+        let credit_bytes =
+            match signing_payload(self.codec.as_ref(), &self.format, &proof.signed_credit) {
+                Ok(bytes) => bytes,
+                Err(_) => return Err(Error::NetworkOther("Could not serialise transfer".into())),
+            };
         // Check if proof is signed by our peers.
-        let public_key = sn_data_types::PublicKey::Bls(self.peer_replicas.public_key());
+        let public_key = self.peer_key;
         let valid_debit = public_key.verify(&proof.debit_sig, &debit_bytes).is_ok();
         let valid_credit = public_key.verify(&proof.credit_sig, &credit_bytes).is_ok();
         if valid_debit && valid_credit {
@@ -359,7 +1590,8 @@ impl WalletReplica {
         }
 
         // If it's not signed with our peers' public key, we won't consider it valid.
-        Err(Error::InvalidSignature)
+        self.report_verify_failure(VerifyKind::RegisteredProof);
+        Err(self.signature_error())
     }
 
     /// Verify that this is a valid _propagated_
@@ -369,41 +1601,2427 @@ impl WalletReplica {
         proof: &CreditAgreementProof,
         past_key: F,
     ) -> Result<()> {
-        // Check that the proof corresponds to a public key set of some Replicas.
-        match bincode::serialize(&proof.signed_credit) {
-            Err(_) => Err(Error::NetworkOther("Could not serialise transfer".into())),
-            Ok(credit_bytes) => {
-                // Check if it is from our group.
-                let our_key = sn_data_types::PublicKey::Bls(self.peer_replicas.public_key());
-                if our_key
-                    .verify(&proof.debiting_replicas_sig, &credit_bytes)
-                    .is_ok()
-                {
-                    return Ok(());
-                }
+        // Check if it is from our group.
+        let our_key = self.peer_key;
+        if verify_credit_proof_under(self.codec.as_ref(), proof, &[our_key], &self.format).is_ok() {
+            return Ok(());
+        } else if self.strict_verification {
+            self.report_verify_failure(VerifyKind::PropagatedProof);
+            return Err(self.signature_error());
+        }
 
-                // Check if proof is signed with an older key
-                let public_key = past_key()?;
-                let valid_credit = public_key
-                    .verify(&proof.debiting_replicas_sig, &credit_bytes)
-                    .is_ok();
-                if valid_credit {
-                    return Ok(());
-                }
+        // Check if proof is signed with an older key,
+        // or by any of the known groups of Replicas.
+        // TODO: Check retrospectively(using SectionProofChain) for known groups also
+        let mut keys = vec![past_key()?];
+        keys.extend(self.known_group_keys());
+        if verify_credit_proof_under(self.codec.as_ref(), proof, &keys, &self.format).is_ok() {
+            return Ok(());
+        }
+        self.report_verify_failure(VerifyKind::PropagatedProof);
+        keys.push(our_key);
+        let mismatch = format_mismatch(
+            self.codec.as_ref(),
+            &keys,
+            &proof.debiting_replicas_sig,
+            &proof.signed_credit,
+            &self.format,
+        );
+        Err(mismatch.unwrap_or_else(|| self.signature_error()))
+    }
 
-                // TODO: Check retrospectively(using SectionProofChain) for known groups also
-                // Check all known groups of Replicas.
-                for set in &self.other_groups {
-                    let debiting_replicas = sn_data_types::PublicKey::Bls(set.public_key());
-                    let result =
-                        debiting_replicas.verify(&proof.debiting_replicas_sig, &credit_bytes);
-                    if result.is_ok() {
-                        return Ok(());
-                    }
-                }
-                // If we don't know the public key this was signed with, we won't consider it valid.
-                Err(Error::InvalidSignature)
+    /// The error of a proof that does not verify.
+    /// With a network id set, it can as well be a proof of another network.
+    fn signature_error(&self) -> Error {
+        if self.format.network_id.is_some() {
+            Error::NetworkOther("wrong network".into())
+        } else if !self.format.context.is_empty() {
+            Error::NetworkOther("wrong context".into())
+        } else {
+            Error::InvalidSignature
+        }
+    }
+
+    /// Invokes the hook, if any, with the context of the failure.
+    fn report_verify_failure(&self, kind: VerifyKind) {
+        if let Some(hook) = &self.on_verify_failure.0 {
+            hook(&VerifyContext {
+                kind,
+                wallet: self.id,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        actor_payloads, build_transfer_proof, proof_size_bytes, transfer_proof_size_bytes,
+        ReplicaSigning,
+    };
+    use serde::de::DeserializeOwned;
+    use sn_data_types::{
+        Credit, Keypair, Signature, SignatureShare, SignedTransfer, TransferPropagated,
+        TransferValidated,
+    };
+    use std::sync::Mutex;
+    use threshold_crypto::{poly::Poly, IntoFr, SecretKeySet};
+    use xor_name::XorName;
+
+    /// A codec tagging the encoded values, so that its encoding differs from bincode.
+    #[derive(Debug, Clone)]
+    struct TaggedCodec;
+
+    const TAG: &[u8] = b"tagged";
+
+    impl Codec for TaggedCodec {
+        fn serialize_into<T: Serialize + ?Sized>(
+            &self,
+            buf: &mut Vec<u8>,
+            value: &T,
+        ) -> Result<()> {
+            let mut tagged = TAG.to_vec();
+            tagged.extend(BincodeCodec.serialize(value)?);
+            *buf = tagged;
+            Ok(())
+        }
+
+        fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T> {
+            if !bytes.starts_with(TAG) {
+                return Err(Error::from("untagged"));
+            }
+            bincode::deserialize(&bytes[TAG.len()..]).map_err(|e| Error::from(e.to_string()))
+        }
+    }
+
+    #[derive(Debug)]
+    struct MockClock(Mutex<SystemTime>);
+
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            let mut now = self.0.lock().unwrap();
+            *now += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn returns_events_since_counter() -> Result<()> {
+        // Arrange
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+            get_propagated(&sk_set, id, 20)?,
+            get_registered(&sk_set, &keypair, 1, 30)?,
+            get_propagated(&sk_set, id, 40)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history.clone())?;
+        let mut lagging = get_replica_from_history(&sk_set, id, history[..2].to_vec())?;
+
+        // Act
+        let events = replica
+            .events_since(Some(0))
+            .ok_or_else(|| Error::from("Expected a known counter"))?;
+        for event in events.clone() {
+            lagging.apply(event)?;
+        }
+
+        // Assert
+        assert_eq!(events, history[2..].to_vec());
+        assert_eq!(lagging.balance(), replica.balance());
+        assert_eq!(lagging.wallet.next_debit(), replica.wallet.next_debit());
+        assert_eq!(replica.events_since(Some(1)), Some(history[4..].to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn returns_no_events_since_unknown_counter() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let peer_replicas = sk_set.public_keys();
+        // The debit at counter 0 was registered before the snapshot.
+        let mut replica = WalletReplica::from_snapshot(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            Default::default(),
+            Wallet::from(id, Money::from_nano(100), 1, Default::default()),
+            Some(0),
+            Arc::new(SystemClock),
+        );
+        replica.apply(get_registered(&sk_set, &keypair, 1, 10)?)?;
+
+        assert_eq!(replica.events_since(Some(0)), None);
+        assert_eq!(replica.events_since(Some(5)), None);
+        Ok(())
+    }
+
+    #[test]
+    fn returns_events_before_first_debit() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_propagated(&sk_set, id, 20)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history.clone())?;
+
+        assert_eq!(replica.events_since(None), Some(history));
+        Ok(())
+    }
+
+    #[test]
+    fn has_no_last_applied_counter_without_debits() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_keypair().public_key();
+        let history = vec![get_propagated(&sk_set, id, 100)?];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+        assert_eq!(replica.last_applied_counter(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn returns_last_applied_counter() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+        assert_eq!(replica.last_applied_counter(), Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn validates_against_snapshot() -> Result<()> {
+        // Arrange
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let old_snapshot = replica
+            .wallet()
+            .ok_or_else(|| Error::Unexpected("Missing wallet".to_string()))?;
+        // Spend the entire balance.
+        replica.apply(get_registered(&sk_set, &keypair, 0, 100)?)?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 50, get_random_pk())?;
+
+        // Act
+        let against_old = replica.validate_against(&old_snapshot, &signed_debit, &signed_credit);
+        let against_current = replica.validate(&signed_debit, &signed_credit);
+
+        // Assert
+        assert!(against_old.is_ok());
+        assert!(matches!(against_current, Err(Error::InsufficientBalance)));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_debit_for_other_wallet_as_wrong_replica() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_keypair().public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, signed_credit) =
+            get_signed_transfer(&get_keypair(), 0, 50, get_random_pk())?;
+
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(
+            matches!(result, Err(Error::NetworkOther(ref msg)) if msg.starts_with("Wrong replica"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn iterates_known_group_keys() -> Result<()> {
+        let sk_set = get_sk_set();
+        let mut replica = get_replica_from_history(&sk_set, get_random_pk(), vec![])?;
+        let first = get_sk_set().public_keys();
+        let second = get_sk_set().public_keys();
+        for group in [first.clone(), second.clone()] {
+            replica.apply(ReplicaEvent::KnownGroupAdded(KnownGroupAdded { group }))?;
+        }
+
+        let keys: HashSet<_> = replica.known_group_keys().collect();
+
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&PublicKey::Bls(first.public_key())));
+        assert!(keys.contains(&PublicKey::Bls(second.public_key())));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_only_most_recent_pending_records() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        replica.set_recent_pending_capacity(2);
+
+        for counter in 0..3 {
+            replica.apply(get_validated(&sk_set, &keypair, counter, 10)?)?;
+        }
+
+        let counters: Vec<_> = replica
+            .recent_pending()
+            .iter()
+            .map(|record| record.counter)
+            .collect();
+        assert_eq!(counters, vec![1, 2]);
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_genesis() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let genesis = get_genesis_proof(&sk_set, id, 100)?;
+
+        let result = replica.genesis(&genesis, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_diagnostics() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let genesis = get_genesis_proof(&sk_set, id, 100)?;
+        let history = vec![
+            get_propagated_proof(&sk_set, genesis)?,
+            get_validated(&sk_set, &keypair, 0, 10)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        let diagnostics = replica.diagnostics();
+
+        let id_hex: String = try_serialize(&id)?
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            ReplicaDiagnostics {
+                id: id_hex,
+                balance: Money::from_nano(90),
+                next_counter: 1,
+                pending_debit: Some(0),
+                known_group_count: 0,
+                genesised: true,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn is_genesised_after_genesis_until_the_end() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        assert!(!replica.is_genesised());
+
+        let genesis = get_genesis_proof(&sk_set, id, 100)?;
+        replica.apply(get_propagated_proof(&sk_set, genesis)?)?;
+        assert!(replica.is_genesised());
+
+        replica.apply(get_registered(&sk_set, &keypair, 0, 100)?)?;
+        assert_eq!(replica.balance(), Money::zero());
+        assert!(replica.is_genesised());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_genesis_of_known_group() -> Result<()> {
+        let sk_set = get_sk_set();
+        let known_set = get_sk_set();
+        let id = get_random_pk();
+        let history = vec![ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: known_set.public_keys(),
+        })];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+        let genesis = get_genesis_proof(&known_set, id, 100)?;
+        assert!(matches!(
+            replica.receive_propagated(&genesis, || Ok(get_random_pk())),
+            Ok(Some(()))
+        ));
+
+        let result = replica.genesis(&genesis, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Err(Error::InvalidOperation)));
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_genesis_of_get_genesis() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let genesis = crate::get_genesis(100, id)?;
+        let ghost_key = PublicKey::Bls(genesis.debiting_replicas_keys.public_key());
+
+        let result = replica.genesis(&genesis, || Ok(ghost_key));
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_genesis_not_signed_by_embedded_key_set() -> Result<()> {
+        let sk_set = get_sk_set();
+        let known_set = get_sk_set();
+        let id = get_random_pk();
+        let history = vec![ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: known_set.public_keys(),
+        })];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+        let mut genesis = get_genesis_proof(&known_set, id, 100)?;
+        genesis.debiting_replicas_keys = get_sk_set().public_keys();
+
+        let result = replica.genesis(&genesis, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Err(Error::InvalidOperation)));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_genesis_with_non_default_credit_id() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 100, id)?;
+        let genesis_like = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.genesis(&genesis_like, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Err(Error::InvalidOperation)));
+        Ok(())
+    }
+
+    #[test]
+    fn audits_consistent_balance() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 30)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        replica.audit_balance()
+    }
+
+    #[test]
+    fn detects_balance_mismatch() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 30)?,
+        ];
+        let mut replica = get_replica_from_history(&sk_set, id, history)?;
+        let snapshot: WalletSnapshot = replica.wallet.to_owned().into();
+        replica.wallet = Wallet::from(id, Money::from_nano(50), 1, snapshot.credit_ids);
+
+        let result = replica.audit_balance();
+
+        match result {
+            Err(Error::NetworkOther(msg)) => {
+                assert_eq!(msg, "balance mismatch: cached 50, derived 70")
             }
+            _ => panic!("Expected a balance mismatch, got {:?}", result),
         }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_degenerate_peer_replicas() -> Result<()> {
+        let id = get_random_pk();
+        let try_from = |sk_set: SecretKeySet| {
+            let peer_replicas = sk_set.public_keys();
+            WalletReplica::try_from_snapshot(
+                id,
+                peer_replicas.public_key_share(0),
+                KeyIndex(0),
+                peer_replicas,
+                Default::default(),
+                Wallet::new(id),
+                None,
+                Arc::new(SystemClock),
+            )
+        };
+
+        assert!(try_from(get_sk_set()).is_ok());
+        let degenerate = SecretKeySet::from(Poly::monomial(1));
+        assert!(matches!(try_from(degenerate), Err(Error::InvalidOperation)));
+        // p(x) = 1 - 3x + x^2 has p(1) = p(2), i.e. the shares at index 0 and 1 are the same.
+        let coeffs = vec![1i64.into_fr(), (-3i64).into_fr(), 1i64.into_fr()];
+        let duplicate_shares = SecretKeySet::from(Poly::from(coeffs));
+        assert!(matches!(
+            try_from(duplicate_shares),
+            Err(Error::InvalidOperation)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_key_share_not_at_our_index() {
+        let id = get_random_pk();
+        let peer_replicas = get_sk_set().public_keys();
+
+        let result = WalletReplica::try_from_snapshot(
+            id,
+            peer_replicas.public_key_share(1),
+            KeyIndex(0),
+            peer_replicas,
+            Default::default(),
+            Wallet::new(id),
+            None,
+            Arc::new(SystemClock),
+        );
+
+        assert!(matches!(result, Err(Error::InvalidOperation)));
+    }
+
+    #[test]
+    fn expires_unregistered_pending_debit() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let clock = Arc::new(MockClock(Mutex::new(SystemTime::UNIX_EPOCH)));
+        let peer_replicas = sk_set.public_keys();
+        let mut replica = WalletReplica::from_snapshot(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            Default::default(),
+            Wallet::from(id, Money::from_nano(100), 0, Default::default()),
+            None,
+            clock.clone(),
+        );
+        replica.set_pending_debit_ttl(Some(Duration::from_secs(60)));
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        assert!(replica.validate(&signed_debit, &signed_credit).is_err());
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!replica.expire_pending_debit());
+
+        clock.advance(Duration::from_secs(1));
+        assert!(replica.expire_pending_debit());
+        assert!(matches!(
+            replica.validate(&signed_debit, &signed_credit),
+            Ok(Some(()))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn registers_built_transfer_proof() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let debit_shares = get_shares(&sk_set, &try_serialize(&signed_debit)?);
+        let credit_shares = get_shares(&sk_set, &try_serialize(&signed_credit)?);
+
+        let proof = build_transfer_proof(
+            &BincodeCodec,
+            signed_debit,
+            signed_credit,
+            debit_shares,
+            credit_shares,
+            &sk_set.public_keys(),
+            &PayloadFormat::default(),
+        )?;
+        let result = replica.register(&proof, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Ok(Some(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn registers_transfer_proof_built_under_format() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let format = PayloadFormat {
+            network_id: Some([1; 32]),
+            ..Default::default()
+        };
+        replica.set_network_id(format.network_id);
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let debit_shares = get_shares(
+            &sk_set,
+            &signing_payload(&BincodeCodec, &format, &signed_debit)?,
+        );
+        let credit_shares = get_shares(
+            &sk_set,
+            &signing_payload(&BincodeCodec, &format, &signed_credit)?,
+        );
+
+        let proof = build_transfer_proof(
+            &BincodeCodec,
+            signed_debit,
+            signed_credit,
+            debit_shares,
+            credit_shares,
+            &sk_set.public_keys(),
+            &format,
+        )?;
+        let result = replica.register(&proof, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Ok(Some(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn cannot_build_transfer_proof_below_quorum() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let debit_shares = get_shares(&sk_set, &try_serialize(&signed_debit)?);
+        let mut credit_shares = get_shares(&sk_set, &try_serialize(&signed_credit)?);
+        let _ = credit_shares.remove(&0);
+
+        let result = build_transfer_proof(
+            &BincodeCodec,
+            signed_debit,
+            signed_credit,
+            debit_shares,
+            credit_shares,
+            &sk_set.public_keys(),
+            &PayloadFormat::default(),
+        );
+
+        assert!(matches!(result, Err(Error::NetworkOther(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn verifies_cached_bytes_like_serialised() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let (_, other_credit) = get_signed_transfer(&get_keypair(), 0, 10, get_random_pk())?;
+
+        for (debit, credit) in &[
+            (signed_debit.clone(), signed_credit),
+            (signed_debit, other_credit),
+        ] {
+            let serialised = replica.verify_actor_signature(debit, credit);
+            let cached = replica.verify_actor_signature_bytes(
+                debit,
+                credit,
+                &try_serialize(&debit.debit)?,
+                &try_serialize(&credit.credit)?,
+            );
+            assert_eq!(serialised.is_ok(), cached.is_ok());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn replaying_genesis_is_no_change() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let genesis = get_genesis_proof(&sk_set, id, 100)?;
+        let history = vec![get_propagated_proof(&sk_set, genesis.clone())?];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        let result = replica.genesis(&genesis, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Ok(None)));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_conflicting_second_genesis() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let genesis = get_genesis_proof(&sk_set, id, 100)?;
+        let history = vec![get_propagated_proof(&sk_set, genesis)?];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+        let other_genesis = get_genesis_proof(&sk_set, id, 200)?;
+
+        let result = replica.genesis(&other_genesis, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Err(Error::InvalidOperation)));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_proof_signed_under_other_wire_version() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        replica.set_wire_version(2);
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        // Signed under the legacy version 1.
+        let proof = get_transfer_proof(&sk_set, signed_debit, signed_credit)?;
+
+        let result = replica.register(&proof, || Ok(get_random_pk()));
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(
+                msg,
+                "Wire version mismatch: signed under version 1, but this replica uses version 2."
+            ),
+            _ => panic!("Expected a wire version mismatch, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_proof_of_other_context() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let payments = PayloadFormat {
+            context: b"payments".to_vec(),
+            ..Default::default()
+        };
+        let credit_bytes = signing_payload(&BincodeCodec, &payments, &signed_credit)?;
+        let proof = CreditAgreementProof {
+            debiting_replicas_sig: sign_with_set(&sk_set, &credit_bytes)?,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+
+        replica.set_context(payments.context);
+        let result = replica.receive_propagated(&proof, || Ok(get_random_pk()));
+        assert!(matches!(result, Ok(Some(()))));
+
+        replica.set_context(b"rewards".to_vec());
+        let result = replica.receive_propagated(&proof, || Ok(get_random_pk()));
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(msg, "wrong context"),
+            _ => panic!("Expected a wrong context, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_proof_signed_under_other_non_legacy_wire_version() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        replica.set_wire_version(3);
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let v2 = PayloadFormat {
+            wire_version: 2,
+            ..Default::default()
+        };
+        let proof = TransferAgreementProof {
+            debit_sig: sign_with_set(
+                &sk_set,
+                &signing_payload(&BincodeCodec, &v2, &signed_debit)?,
+            )?,
+            credit_sig: sign_with_set(
+                &sk_set,
+                &signing_payload(&BincodeCodec, &v2, &signed_credit)?,
+            )?,
+            signed_debit,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+
+        let result = replica.register(&proof, || Ok(get_random_pk()));
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(
+                msg,
+                "Wire version mismatch: signed under version 2, but this replica uses version 3."
+            ),
+            _ => panic!("Expected a wire version mismatch, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_proof_signed_without_our_network() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        replica.set_wire_version(2);
+        replica.set_network_id(Some([1; 32]));
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let v2 = PayloadFormat {
+            wire_version: 2,
+            ..Default::default()
+        };
+        let credit_bytes = signing_payload(&BincodeCodec, &v2, &signed_credit)?;
+        let proof = CreditAgreementProof {
+            debiting_replicas_sig: sign_with_set(&sk_set, &credit_bytes)?,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+
+        let result = replica.receive_propagated(&proof, || Ok(get_random_pk()));
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(
+                msg,
+                "Network mismatch: signed under no network, but this replica is bound to one."
+            ),
+            _ => panic!("Expected a network mismatch, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_proof_of_other_network() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let network_a = PayloadFormat {
+            network_id: Some([1; 32]),
+            ..Default::default()
+        };
+        let credit_bytes = signing_payload(&BincodeCodec, &network_a, &signed_credit)?;
+        let proof = CreditAgreementProof {
+            debiting_replicas_sig: sign_with_set(&sk_set, &credit_bytes)?,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        };
+
+        replica.set_network_id(network_a.network_id);
+        let result = replica.receive_propagated(&proof, || Ok(get_random_pk()));
+        assert!(matches!(result, Ok(Some(()))));
+
+        replica.set_network_id(Some([2; 32]));
+        let result = replica.receive_propagated(&proof, || Ok(get_random_pk()));
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(msg, "wrong network"),
+            _ => panic!("Expected a wrong network, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn self_transfer_fails_signature_verification() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, id)?;
+
+        let result = replica.verify_actor_signature(&signed_debit, &signed_credit);
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_serialised_proof_sizes() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit.clone())?;
+        let transfer_proof = get_transfer_proof(&sk_set, signed_debit, signed_credit)?;
+
+        assert_eq!(
+            proof_size_bytes(&credit_proof)?,
+            try_serialize(&credit_proof)?.len()
+        );
+        assert_eq!(
+            transfer_proof_size_bytes(&transfer_proof)?,
+            try_serialize(&transfer_proof)?.len()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_past_key_proof_in_lenient_mode() -> Result<()> {
+        let sk_set = get_sk_set();
+        let past_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let proof = get_past_key_proof(&past_set, id)?;
+
+        let result = replica.receive_propagated(&proof, || {
+            Ok(PublicKey::Bls(past_set.public_keys().public_key()))
+        });
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_past_key_proof_in_strict_mode() -> Result<()> {
+        let sk_set = get_sk_set();
+        let past_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        replica.set_strict_verification(true);
+        let proof = get_past_key_proof(&past_set, id)?;
+
+        let result = replica.receive_propagated(&proof, || {
+            Ok(PublicKey::Bls(past_set.public_keys().public_key()))
+        });
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    fn get_past_key_proof(
+        past_set: &SecretKeySet,
+        recipient: PublicKey,
+    ) -> Result<CreditAgreementProof> {
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, recipient)?;
+        get_credit_proof(past_set, signed_credit)
+    }
+
+    #[test]
+    fn rolls_back_batch_with_failing_event() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let original = replica.clone();
+        let batch = vec![
+            get_propagated(&sk_set, id, 100)?,
+            // Exceeds the balance, so fails to apply.
+            get_registered(&sk_set, &keypair, 0, 1000)?,
+            get_propagated(&sk_set, id, 10)?,
+        ];
+
+        let result = replica.apply_all(batch);
+
+        assert!(result.is_err());
+        assert_eq!(replica, original);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_credit_exceeding_remaining_capacity() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_near_max_replica(&sk_set, id, 10);
+        assert_eq!(replica.remaining_capacity(), Money::from_nano(10));
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 11, id)?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.receive_propagated(&credit_proof, || Ok(get_random_pk()));
+        assert!(matches!(result, Err(Error::ExcessiveValue)));
+
+        let result = replica.apply(get_propagated_proof(&sk_set, credit_proof)?);
+        assert!(matches!(result, Err(Error::ExcessiveValue)));
+        assert_eq!(replica.remaining_capacity(), Money::from_nano(10));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_credit_for_other_recipient() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, get_random_pk())?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.receive_propagated(&credit_proof, || Ok(get_random_pk()));
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(msg, "credit recipient mismatch"),
+            _ => panic!("Expected a recipient mismatch, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_credit_below_dust_threshold() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        replica.set_dust_threshold(Money::from_nano(10));
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 9, id)?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.receive_propagated(&credit_proof, || Ok(get_random_pk()));
+
+        match result {
+            Err(Error::NetworkOther(msg)) => assert_eq!(msg, "below dust threshold"),
+            _ => panic!("Expected a dust credit, got {:?}", result),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn applied_credit_below_dust_threshold_is_no_change() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let applied = get_propagated(&sk_set, id, 9)?;
+        let credit_proof = match &applied {
+            ReplicaEvent::TransferPropagated(e) => e.credit_proof.clone(),
+            _ => return Err(Error::from("Expected a propagated credit")),
+        };
+        let mut replica = get_replica_from_history(&sk_set, id, vec![applied])?;
+        replica.set_dust_threshold(Money::from_nano(10));
+
+        let result = replica.receive_propagated(&credit_proof, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Ok(None)));
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_credit_at_dust_threshold() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        replica.set_dust_threshold(Money::from_nano(10));
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.receive_propagated(&credit_proof, || Ok(get_random_pk()));
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_credit_filling_remaining_capacity() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_near_max_replica(&sk_set, id, 10);
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.receive_propagated(&credit_proof, || Ok(get_random_pk()))?;
+        assert_eq!(result, Some(()));
+
+        replica.apply(get_propagated_proof(&sk_set, credit_proof)?)?;
+        assert_eq!(replica.balance(), Money::from_nano(u64::MAX));
+        assert_eq!(replica.remaining_capacity(), Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn rebuilds_equal_replica_from_cloned_wallet() {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let peer_replicas = sk_set.public_keys();
+        let get_replica = |wallet| {
+            WalletReplica::from_snapshot(
+                id,
+                peer_replicas.public_key_share(0),
+                KeyIndex(0),
+                peer_replicas.clone(),
+                Default::default(),
+                wallet,
+                Some(1),
+                Arc::new(SystemClock),
+            )
+        };
+        let credit_ids = vec![XorName::random()].into_iter().collect();
+        let original = get_replica(Wallet::from(id, Money::from_nano(100), 2, credit_ids));
+
+        let rebuilt = get_replica(original.clone_wallet());
+
+        assert_eq!(rebuilt, original);
+    }
+
+    #[test]
+    fn reports_duplicate_known_group() -> Result<()> {
+        let sk_set = get_sk_set();
+        let mut replica = get_replica_from_history(&sk_set, get_random_pk(), vec![])?;
+        let event = ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: get_sk_set().public_keys(),
+        });
+
+        assert!(replica.apply_idempotent(event.clone())?);
+        assert!(!replica.apply_idempotent(event)?);
+        assert_eq!(replica.known_group_keys().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn credit_increases_balance_delta() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+
+        let delta = replica.apply_with_balance_delta(get_propagated(&sk_set, id, 100)?)?;
+
+        assert_eq!(delta, (Money::zero(), Money::from_nano(100)));
+        Ok(())
+    }
+
+    #[test]
+    fn debit_decreases_balance_delta() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+
+        let delta = replica.apply_with_balance_delta(get_registered(&sk_set, &keypair, 0, 30)?)?;
+
+        assert_eq!(delta, (Money::from_nano(100), Money::from_nano(70)));
+        Ok(())
+    }
+
+    #[test]
+    fn group_added_leaves_balance_delta_unchanged() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let event = ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: get_sk_set().public_keys(),
+        });
+
+        let delta = replica.apply_with_balance_delta(event)?;
+
+        assert_eq!(delta, (Money::from_nano(100), Money::from_nano(100)));
+        Ok(())
+    }
+
+    #[test]
+    fn validates_client_signed_debit() -> Result<()> {
+        validates_debit_signed_by(Keypair::new_ed25519(&mut rand::thread_rng()))
+    }
+
+    #[test]
+    fn validates_node_signed_debit() -> Result<()> {
+        validates_debit_signed_by(Keypair::new_bls(&mut rand::thread_rng()))
+    }
+
+    #[test]
+    fn rejects_signature_of_other_scheme_than_sender_key() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (mut signed_debit, mut signed_credit) =
+            get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let bls_keypair = Keypair::new_bls(&mut rand::thread_rng());
+        signed_debit.actor_signature = bls_keypair.sign(&try_serialize(&signed_debit.debit)?);
+        signed_credit.actor_signature = bls_keypair.sign(&try_serialize(&signed_credit.credit)?);
+
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    fn validates_debit_signed_by(keypair: Keypair) -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+
+        replica.verify_actor_signature(&signed_debit, &signed_credit)?;
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn lists_distinct_paid_recipients() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let (alice, bob) = (get_random_pk(), get_random_pk());
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered_to(&sk_set, &keypair, 0, alice)?,
+            get_registered_to(&sk_set, &keypair, 1, bob)?,
+            get_registered_to(&sk_set, &keypair, 2, alice)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        let recipients = replica.paid_recipients();
+
+        assert_eq!(recipients, vec![alice, bob].into_iter().collect());
+        Ok(())
+    }
+
+    fn get_registered_to(
+        sk_set: &SecretKeySet,
+        keypair: &Keypair,
+        counter: u64,
+        recipient: PublicKey,
+    ) -> Result<ReplicaEvent> {
+        let (signed_debit, signed_credit) = get_signed_transfer(keypair, counter, 10, recipient)?;
+        let transfer_proof = get_transfer_proof(sk_set, signed_debit, signed_credit)?;
+        Ok(ReplicaEvent::TransferRegistered(TransferRegistered {
+            transfer_proof,
+        }))
+    }
+
+    #[test]
+    fn counts_events_applied_from_history() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let peer_replicas = sk_set.public_keys();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_validated(&sk_set, &keypair, 0, 10)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+
+        let (replica, applied) = WalletReplica::from_history_counted(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            history.clone(),
+        )?;
+
+        assert_eq!(applied, history.len());
+        assert_eq!(replica.balance(), Money::from_nano(90));
+        Ok(())
+    }
+
+    #[test]
+    fn does_not_count_duplicate_events_from_history() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let peer_replicas = sk_set.public_keys();
+        let propagated = get_propagated(&sk_set, id, 100)?;
+        let registered = get_registered(&sk_set, &keypair, 0, 10)?;
+        let history = vec![
+            propagated.clone(),
+            propagated,
+            get_validated(&sk_set, &keypair, 0, 10)?,
+            registered.clone(),
+            registered,
+        ];
+
+        let (replica, applied) = WalletReplica::from_history_counted(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            history.clone(),
+        )?;
+
+        assert_eq!(applied, history.len() - 2);
+        assert_eq!(replica.balance(), Money::from_nano(90));
+        Ok(())
+    }
+
+    #[test]
+    fn verified_replay_rejects_forged_transfer() -> Result<()> {
+        let sk_set = get_sk_set();
+        let forger_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let peer_replicas = sk_set.public_keys();
+        let trusted_keys = vec![PublicKey::Bls(peer_replicas.public_key())];
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+            get_registered(&forger_set, &keypair, 1, 10)?,
+        ];
+
+        let result = WalletReplica::from_history_verified(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas.clone(),
+            history[..2].to_vec(),
+            &trusted_keys,
+        );
+        assert!(result.is_ok());
+
+        let result = WalletReplica::from_history_verified(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            history,
+            &trusted_keys,
+        );
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn cancels_unregistered_pending_debit() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_validated(&sk_set, &keypair, 0, 10)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+        let mut replica = get_replica_from_history(&sk_set, id, history)?;
+        assert!(!replica.cancel_pending_debit());
+
+        replica.apply(get_validated(&sk_set, &keypair, 1, 60)?)?;
+        assert_eq!(replica.spendable_balance(), Money::from_nano(30));
+
+        assert!(replica.cancel_pending_debit());
+        assert_eq!(replica.spendable_balance(), Money::from_nano(90));
+        assert_eq!(replica.next_expected_counter(), 1);
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 1, 60, get_random_pk())?;
+        assert!(matches!(
+            replica.validate(&signed_debit, &signed_credit),
+            Ok(Some(()))
+        ));
+        assert!(!replica.cancel_pending_debit());
+        Ok(())
+    }
+
+    #[test]
+    fn tells_which_counters_are_registered() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+            get_registered(&sk_set, &keypair, 1, 10)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        assert!(replica.is_counter_registered(0));
+        assert!(replica.is_counter_registered(1));
+        assert!(!replica.is_counter_registered(2));
+        Ok(())
+    }
+
+    #[test]
+    fn validates_debit_meeting_actor_threshold() -> Result<()> {
+        let sk_set = get_sk_set();
+        let actors = SecretKeySet::random(2, &mut rand::thread_rng());
+        let (debit, credit) = get_multisig_transfer(&actors, 0..2)?;
+        let id = debit.debit.sender();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+
+        let result = replica.validate_multisig(&debit, &credit, &actors.public_keys(), 2);
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_debit_below_actor_threshold() -> Result<()> {
+        let sk_set = get_sk_set();
+        let actors = SecretKeySet::random(2, &mut rand::thread_rng());
+        let (debit, credit) = get_multisig_transfer(&actors, 0..1)?;
+        let id = debit.debit.sender();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+
+        let result = replica.validate_multisig(&debit, &credit, &actors.public_keys(), 2);
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    fn get_multisig_transfer(
+        actors: &SecretKeySet,
+        signers: std::ops::Range<usize>,
+    ) -> Result<(MultiSigDebit, MultiSigCredit)> {
+        let debit = Debit {
+            id: Dot::new(PublicKey::Bls(actors.public_keys().public_key()), 0),
+            amount: Money::from_nano(10),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient: get_random_pk(),
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        let sign = |data: Vec<u8>| -> Vec<SignatureShare> {
+            signers
+                .clone()
+                .map(|index| SignatureShare {
+                    index,
+                    share: actors.secret_key_share(index).sign(&data),
+                })
+                .collect()
+        };
+        Ok((
+            MultiSigDebit {
+                actor_signatures: sign(try_serialize(&debit)?),
+                debit,
+            },
+            MultiSigCredit {
+                actor_signatures: sign(try_serialize(&credit)?),
+                credit,
+            },
+        ))
+    }
+
+    #[test]
+    fn reverts_first_pending_debit_to_none() -> Result<()> {
+        let mut replica = get_replica_from_history(&get_sk_set(), get_random_pk(), vec![])?;
+        replica.pending_debit = Some(0);
+
+        replica.revert_pending();
+
+        assert_eq!(replica.pending_debit, None);
+        Ok(())
+    }
+
+    #[test]
+    fn reverts_pending_debit_to_previous() -> Result<()> {
+        let mut replica = get_replica_from_history(&get_sk_set(), get_random_pk(), vec![])?;
+        replica.pending_debit = Some(5);
+
+        replica.revert_pending();
+
+        assert_eq!(replica.pending_debit, Some(4));
+        Ok(())
+    }
+
+    #[test]
+    fn round_trips_proof_through_alternative_codec() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let codec = Arc::new(TaggedCodec);
+        let peer_replicas = sk_set.public_keys();
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+
+        // Sign with a quorum of Replicas sharing the codec.
+        let mut debit_shares = BTreeMap::new();
+        let mut credit_shares = BTreeMap::new();
+        for index in 0..=sk_set.threshold() {
+            let signing = ReplicaSigning::new_with_codec(
+                sk_set.secret_key_share(index),
+                KeyIndex(index),
+                peer_replicas.clone(),
+                codec.clone(),
+            );
+            let (debit_sig, credit_sig) = signing
+                .sign_transfer(&SignedTransfer {
+                    debit: signed_debit.clone(),
+                    credit: signed_credit.clone(),
+                })?
+                .ok_or_else(|| Error::from("Not signed"))?;
+            let _ = debit_shares.insert(index, debit_sig.share);
+            let _ = credit_shares.insert(index, credit_sig.share);
+        }
+        let combine =
+            |shares: BTreeMap<usize, threshold_crypto::SignatureShare>| -> Result<Signature> {
+                let sig = peer_replicas
+                    .combine_signatures(&shares)
+                    .map_err(|e| Error::from(e.to_string()))?;
+                Ok(Signature::Bls(sig))
+            };
+        let proof = TransferAgreementProof {
+            signed_debit,
+            debit_sig: combine(debit_shares)?,
+            signed_credit,
+            credit_sig: combine(credit_shares)?,
+            debiting_replicas_keys: peer_replicas.clone(),
+        };
+
+        let decoded: TransferAgreementProof = codec.deserialize(&codec.serialize(&proof)?)?;
+        assert_eq!(decoded, proof);
+
+        let history = vec![get_propagated(&sk_set, id, 100)?];
+        let mut replica = WalletReplica::from_snapshot_with_codec(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas.clone(),
+            Default::default(),
+            Wallet::new(id),
+            None,
+            Arc::new(SystemClock),
+            codec,
+        );
+        replica.apply_all(history.clone())?;
+        let bincode_replica = get_replica_from_history(&sk_set, id, history)?;
+
+        assert!(matches!(
+            replica.register(&proof, || Ok(get_random_pk())),
+            Ok(Some(_))
+        ));
+        assert!(matches!(
+            bincode_replica.register(&proof, || Ok(get_random_pk())),
+            Err(Error::InvalidSignature)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn flags_registered_burn() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let burn_address = get_random_pk();
+        let history = vec![get_propagated(&sk_set, id, 100)?];
+        let mut replica = get_replica_from_history(&sk_set, id, history)?;
+        replica.set_burn_address(Some((burn_address, BurnPolicy::Flag)));
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, burn_address)?;
+        assert!(replica.validate(&signed_debit, &signed_credit).is_ok());
+        let proof = get_transfer_proof(&sk_set, signed_debit, signed_credit)?;
+
+        let registered = replica
+            .register(&proof, || Ok(get_random_pk()))?
+            .ok_or_else(|| Error::from("Not registered"))?;
+
+        assert!(replica.is_burn(&registered));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_burn_by_policy() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let burn_address = get_random_pk();
+        let history = vec![get_propagated(&sk_set, id, 100)?];
+        let mut replica = get_replica_from_history(&sk_set, id, history)?;
+        replica.set_burn_address(Some((burn_address, BurnPolicy::Reject)));
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, burn_address)?;
+
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn replicas_with_different_known_groups_are_balance_equivalent() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let history = vec![get_propagated(&sk_set, id, 100)?];
+        let replica = get_replica_from_history(&sk_set, id, history.clone())?;
+        let mut other = get_replica_from_history(&sk_set, id, history)?;
+        other.apply(ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: get_sk_set().public_keys(),
+        }))?;
+
+        assert_ne!(replica, other);
+        assert!(replica.balance_equivalent(&other));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_debit_equivocation() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+        let mut replica = get_replica_from_history(&sk_set, id, history)?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 20, get_random_pk())?;
+        let conflicting = get_transfer_proof(&sk_set, signed_debit, signed_credit)?;
+
+        let result = replica.register(&conflicting, || Ok(get_random_pk()));
+        match result {
+            Err(Error::NetworkOther(msg)) => {
+                assert!(msg.starts_with("debit equivocation at counter 0"))
+            }
+            _ => panic!("Expected an equivocation, got {:?}", result),
+        }
+
+        let result = replica.apply(ReplicaEvent::TransferRegistered(TransferRegistered {
+            transfer_proof: conflicting,
+        }));
+        assert!(matches!(result, Err(Error::NetworkOther(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn classifies_credits_for_sync() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let applied = get_propagated(&sk_set, id, 100)?;
+        let present = match &applied {
+            ReplicaEvent::TransferPropagated(e) => e.credit_proof.clone(),
+            _ => return Err(Error::from("Expected a propagated credit")),
+        };
+        let replica = get_replica_from_history(&sk_set, id, vec![applied])?;
+        let (_, new_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let new = get_credit_proof(&sk_set, new_credit)?;
+        let (_, forged_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let forged = get_credit_proof(&get_sk_set(), forged_credit)?;
+
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let classes =
+            replica.classify_credits(&[new, present, forged], || Ok(get_random_pk()), deadline);
+
+        assert!(matches!(
+            classes.as_slice(),
+            [
+                CreditClassification::New,
+                CreditClassification::Duplicate,
+                CreditClassification::Invalid(Error::InvalidSignature)
+            ]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn identifies_unknown_signer() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (_, credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let ours = get_credit_proof(&sk_set, credit.clone())?;
+        let unknown_set = get_sk_set();
+        let unknown = get_credit_proof(&unknown_set, credit)?;
+        let mut forged = unknown.clone();
+        forged.debiting_replicas_keys = get_sk_set().public_keys();
+
+        assert_eq!(replica.identify_unknown_signer(&ours), None);
+        assert_eq!(
+            replica.identify_unknown_signer(&unknown),
+            Some(unknown_set.public_keys())
+        );
+        assert_eq!(replica.identify_unknown_signer(&forged), None);
+        Ok(())
+    }
+
+    #[test]
+    fn times_out_classifying_credits() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let proofs = (0..3)
+            .map(|_| {
+                let (_, credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+                get_credit_proof(&sk_set, credit)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let classes = replica.classify_credits(&proofs, || Ok(get_random_pk()), Instant::now());
+
+        assert!(matches!(
+            classes.as_slice(),
+            [
+                CreditClassification::New,
+                CreditClassification::TimedOut,
+                CreditClassification::TimedOut
+            ]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn returns_wrapped_key_index() -> Result<()> {
+        let sk_set = get_sk_set();
+        let peer_replicas = sk_set.public_keys();
+        let replica = WalletReplica::from_history(
+            get_random_pk(),
+            peer_replicas.public_key_share(1),
+            KeyIndex(1),
+            peer_replicas,
+            vec![],
+        )?;
+
+        assert_eq!(replica.key_index(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn returns_validated_credit_id() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+
+        let credit_id = replica.validate_with_credit_id(&signed_debit, &signed_credit)?;
+
+        assert_eq!(credit_id, Some(*signed_credit.id()));
+        Ok(())
+    }
+
+    #[test]
+    fn prechecks_valid_debit() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, _) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+
+        assert!(matches!(
+            replica.precheck_debit(&signed_debit),
+            Ok(Some(()))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn precheck_rejects_insufficient_balance() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, _) = get_signed_transfer(&keypair, 0, 1000, get_random_pk())?;
+
+        let result = replica.precheck_debit(&signed_debit);
+
+        assert!(matches!(result, Err(Error::InsufficientBalance)));
+        Ok(())
+    }
+
+    #[test]
+    fn precheck_rejects_out_of_order_debit() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (signed_debit, _) = get_signed_transfer(&keypair, 1, 10, get_random_pk())?;
+
+        let result = replica.precheck_debit(&signed_debit);
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tracks_credits_awaiting_application() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let credit_proof = get_credit_proof(&sk_set, signed_credit)?;
+
+        let result = replica.receive_propagated_awaiting(&credit_proof, || Ok(get_random_pk()))?;
+        assert_eq!(result, Some(()));
+        assert_eq!(replica.awaiting_application(), vec![*credit_proof.id()]);
+
+        replica.apply(get_propagated_proof(&sk_set, credit_proof)?)?;
+        assert!(replica.awaiting_application().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_forged_actor_signature() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        let reports = Arc::new(Mutex::new(vec![]));
+        let recorded = reports.clone();
+        replica.set_on_verify_failure(Some(Arc::new(move |context: &VerifyContext| {
+            recorded.lock().unwrap().push(*context)
+        })));
+        let (signed_debit, mut signed_credit) =
+            get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        signed_credit.actor_signature = get_keypair().sign(&try_serialize(&signed_credit.credit)?);
+
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![VerifyContext {
+                kind: VerifyKind::ActorSignature,
+                wallet: keypair.public_key(),
+            }]
+        );
+        Ok(())
+    }
+
+    /// A replica whose wallet can receive exactly `capacity` more.
+    fn get_near_max_replica(sk_set: &SecretKeySet, id: PublicKey, capacity: u64) -> WalletReplica {
+        let peer_replicas = sk_set.public_keys();
+        let balance = Money::from_nano(u64::MAX - capacity);
+        WalletReplica::from_snapshot(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            Default::default(),
+            Wallet::from(id, balance, 0, Default::default()),
+            None,
+            Arc::new(SystemClock),
+        )
+    }
+
+    #[test]
+    fn strict_replica_rejects_debit_ahead_of_sequence() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 2, 10, get_random_pk())?;
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sums_pending_debits() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        replica.set_lookahead(1);
+        assert_eq!(replica.pending_total(), Money::zero());
+
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+        assert_eq!(replica.pending_total(), Money::from_nano(10));
+
+        replica.apply(get_validated(&sk_set, &keypair, 1, 20)?)?;
+        assert_eq!(replica.pending_total(), Money::from_nano(30));
+        assert_eq!(replica.spendable_balance(), Money::from_nano(70));
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_debit_overdrawing_pending_debits() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        replica.set_lookahead(2);
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 60, get_random_pk())?;
+        assert_eq!(replica.validate(&signed_debit, &signed_credit)?, Some(()));
+        replica.apply(get_validated(&sk_set, &keypair, 0, 60)?)?;
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 1, 60, get_random_pk())?;
+        assert!(matches!(
+            replica.validate(&signed_debit, &signed_credit),
+            Err(Error::InsufficientBalance)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn validates_debits_within_lookahead() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        replica.set_lookahead(2);
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 3, 10, get_random_pk())?;
+        assert_eq!(replica.validate(&signed_debit, &signed_credit)?, Some(()));
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 4, 10, get_random_pk())?;
+        assert!(replica.validate(&signed_debit, &signed_credit).is_err());
+
+        // The pending debit catches up once the gap is filled.
+        replica.apply(get_validated(&sk_set, &keypair, 2, 10)?)?;
+        replica.apply(get_validated(&sk_set, &keypair, 1, 10)?)?;
+        assert_eq!(replica.pending_debit, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_validated_debit_behind_pending_debit() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        replica.set_lookahead(2);
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+        replica.apply(get_registered(&sk_set, &keypair, 0, 10)?)?;
+
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+
+        assert_eq!(replica.spendable_balance(), Money::from_nano(90));
+        assert!(replica.missing_counters().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn releases_spendable_balance_when_caught_up_debits_expire() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let clock = Arc::new(MockClock(Mutex::new(SystemTime::UNIX_EPOCH)));
+        let peer_replicas = sk_set.public_keys();
+        let mut replica = WalletReplica::from_snapshot(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            Default::default(),
+            Wallet::from(id, Money::from_nano(100), 0, Default::default()),
+            None,
+            clock.clone(),
+        );
+        replica.set_lookahead(2);
+        replica.set_pending_debit_ttl(Some(Duration::from_secs(60)));
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+        replica.apply(get_validated(&sk_set, &keypair, 2, 10)?)?;
+        replica.apply(get_validated(&sk_set, &keypair, 1, 10)?)?;
+        replica.apply(get_validated(&sk_set, &keypair, 4, 10)?)?;
+        assert_eq!(replica.pending_debit, Some(2));
+        assert_eq!(replica.spendable_balance(), Money::from_nano(60));
+
+        clock.advance(Duration::from_secs(60));
+
+        assert!(replica.expire_pending_debit());
+        assert_eq!(replica.pending_debit, None);
+        assert_eq!(replica.spendable_balance(), Money::from_nano(100));
+        assert!(replica.missing_counters().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn applies_credits_with_single_overflow_check() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let applied = get_propagated(&sk_set, id, u64::MAX - 10)?;
+        let mut replica = get_replica_from_history(&sk_set, id, vec![applied.clone()])?;
+        let proof = |event: ReplicaEvent| match event {
+            ReplicaEvent::TransferPropagated(e) => Ok(e.credit_proof),
+            _ => Err(Error::from("Expected a propagated credit")),
+        };
+        let first = proof(get_propagated(&sk_set, id, 6)?)?;
+        let second = proof(get_propagated(&sk_set, id, 6)?)?;
+
+        let result = replica.apply_credits(vec![first.clone(), second]);
+        assert!(matches!(result, Err(Error::ExcessiveValue)));
+        assert_eq!(replica.balance(), Money::from_nano(u64::MAX - 10));
+
+        let count = replica.apply_credits(vec![first.clone(), proof(applied)?, first])?;
+        assert_eq!(count, 1);
+        assert_eq!(replica.balance(), Money::from_nano(u64::MAX - 4));
+        replica.audit_balance()
+    }
+
+    #[test]
+    fn applies_no_credits_of_a_batch_with_an_invalid_one() -> Result<()> {
+        let sk_set = get_sk_set();
+        let id = get_random_pk();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let mut proofs = vec![];
+        for signer in &[&sk_set, &get_sk_set(), &sk_set] {
+            let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+            proofs.push(get_credit_proof(signer, signed_credit)?);
+        }
+
+        let result = replica.apply_credits(proofs);
+
+        assert!(matches!(result, Err(Error::InvalidSignature)));
+        assert_eq!(replica.balance(), Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn lists_missing_counters() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let genesis = get_propagated(&sk_set, keypair.public_key(), 100)?;
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), vec![genesis])?;
+        replica.set_lookahead(2);
+        assert!(replica.missing_counters().is_empty());
+
+        replica.apply(get_validated(&sk_set, &keypair, 0, 10)?)?;
+        replica.apply(get_validated(&sk_set, &keypair, 2, 10)?)?;
+        assert_eq!(replica.missing_counters(), vec![1]);
+
+        replica.apply(get_validated(&sk_set, &keypair, 1, 10)?)?;
+        assert!(replica.missing_counters().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn adds_only_new_known_groups() -> Result<()> {
+        let sk_set = get_sk_set();
+        let known = get_sk_set().public_keys();
+        let history = vec![ReplicaEvent::KnownGroupAdded(KnownGroupAdded {
+            group: known.clone(),
+        })];
+        let replica = get_replica_from_history(&sk_set, get_random_pk(), history)?;
+        let (first, second) = (get_sk_set().public_keys(), get_sk_set().public_keys());
+
+        let added = replica
+            .add_known_groups(vec![
+                known.clone(),
+                first.clone(),
+                second.clone(),
+                first.clone(),
+            ])?
+            .ok_or_else(|| Error::from("Expected groups to be added"))?;
+
+        let added: Vec<_> = added.into_iter().map(|e| e.group).collect();
+        assert_eq!(added, vec![first, second]);
+        assert_eq!(replica.add_known_groups(vec![known])?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn restores_to_earlier_snapshot() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let history = vec![
+            get_propagated(&sk_set, keypair.public_key(), 100)?,
+            get_registered(&sk_set, &keypair, 0, 10)?,
+        ];
+        let mut replica = get_replica_from_history(&sk_set, keypair.public_key(), history)?;
+        let snapshot = replica
+            .wallet()
+            .ok_or_else(|| Error::from("Expected a wallet"))?;
+        replica.apply(get_validated(&sk_set, &keypair, 1, 20)?)?;
+        replica.apply(get_registered(&sk_set, &keypair, 1, 20)?)?;
+
+        assert!(replica.restore_to(snapshot.clone(), None).is_err());
+        replica.restore_to(snapshot, Some(0))?;
+
+        assert_eq!(replica.balance(), Money::from_nano(90));
+        assert_eq!(replica.clone_wallet().next_debit(), 1);
+        replica.audit_balance()?;
+        Ok(())
+    }
+
+    #[test]
+    fn validates_transfer_signed_over_actor_payloads() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let (debit, credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let mut transfer = SignedTransfer { debit, credit };
+        // Replaces the signatures, to be made over the payloads below.
+        let other = get_keypair();
+        transfer.debit.actor_signature = other.sign(b"debit");
+        transfer.credit.actor_signature = other.sign(b"credit");
+
+        let (debit_bytes, credit_bytes) = actor_payloads(&transfer)?;
+        transfer.debit.actor_signature = keypair.sign(&debit_bytes);
+        transfer.credit.actor_signature = keypair.sign(&credit_bytes);
+
+        let result = replica.validate(&transfer.debit, &transfer.credit);
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn validates_transfer_signed_as_actor() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![get_propagated(&sk_set, id, 100)?];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        let (debit, credit) =
+            replica.next_transfer_template(get_random_pk(), Money::from_nano(10), "asdf".into())?;
+        let (signed_debit, signed_credit) = crate::sign_transfer_as_actor(debit, credit, &keypair)?;
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn emits_validate_span() -> Result<()> {
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        /// Records the names and fields of the new spans.
+        #[derive(Default)]
+        struct SpanRecorder(Arc<Mutex<Vec<(String, Vec<String>)>>>);
+
+        struct FieldNames(Vec<String>);
+
+        impl Visit for FieldNames {
+            fn record_debug(&mut self, field: &Field, _: &dyn Debug) {
+                self.0.push(field.name().to_string());
+            }
+        }
+
+        impl Subscriber for SpanRecorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+                let mut fields = FieldNames(vec![]);
+                span.record(&mut fields);
+                let mut spans = self.0.lock().expect("poisoned lock");
+                spans.push((span.metadata().name().to_string(), fields.0));
+                span::Id::from_u64(spans.len() as u64)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, _: &Event<'_>) {}
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let history = vec![get_propagated(&sk_set, keypair.public_key(), 100)?];
+        let replica = get_replica_from_history(&sk_set, keypair.public_key(), history)?;
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let recorder = SpanRecorder::default();
+        let spans = recorder.0.clone();
+
+        let result = tracing::subscriber::with_default(recorder, || {
+            replica.validate(&signed_debit, &signed_credit)
+        });
+
+        assert!(matches!(result, Ok(Some(()))));
+        let spans = spans.lock().expect("poisoned lock");
+        assert!(spans.contains(&(
+            "validate".to_string(),
+            vec!["wallet".into(), "counter".into(), "amount".into()]
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn validates_with_pending_override() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        let pending = Some((0, Money::from_nano(80)));
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 1, 20, get_random_pk())?;
+        assert!(replica.validate(&signed_debit, &signed_credit).is_err());
+        assert!(matches!(
+            replica.validate_with_pending(&signed_debit, &signed_credit, pending),
+            Ok(Some(()))
+        ));
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 1, 21, get_random_pk())?;
+        assert!(replica
+            .validate_with_pending(&signed_debit, &signed_credit, pending)
+            .is_err());
+
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 21, get_random_pk())?;
+        assert!(replica
+            .validate_with_pending(&signed_debit, &signed_credit, pending)
+            .is_err());
+        assert!(matches!(
+            replica.validate_with_pending(&signed_debit, &signed_credit, None),
+            Ok(Some(()))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn sweeps_entire_balance() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let mut replica = get_replica_from_history(&sk_set, id, vec![])?;
+        let result = replica.sweep_transfer_template(get_random_pk(), "asdf".into());
+        assert!(matches!(result, Err(Error::InsufficientBalance)));
+        replica.apply(get_propagated(&sk_set, id, 100)?)?;
+
+        let (debit, credit) = replica.sweep_transfer_template(get_random_pk(), "asdf".into())?;
+        assert_eq!(debit.amount(), Money::from_nano(100));
+        let signed_debit = SignedDebit {
+            actor_signature: keypair.sign(&try_serialize(&debit)?),
+            debit,
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: keypair.sign(&try_serialize(&credit)?),
+            credit,
+        };
+        assert!(matches!(
+            replica.validate(&signed_debit, &signed_credit),
+            Ok(Some(()))
+        ));
+
+        replica.apply(get_validated(&sk_set, &keypair, 0, 100)?)?;
+        assert_eq!(replica.spendable_balance(), Money::zero());
+        Ok(())
+    }
+
+    #[test]
+    fn validates_transfer_from_template() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let history = vec![
+            get_propagated(&sk_set, id, 100)?,
+            get_validated(&sk_set, &keypair, 0, 10)?,
+        ];
+        let replica = get_replica_from_history(&sk_set, id, history)?;
+
+        let (debit, credit) =
+            replica.next_transfer_template(get_random_pk(), Money::from_nano(10), "asdf".into())?;
+        assert_eq!(debit.id.counter, 1);
+        assert_eq!(debit.sender(), id);
+
+        let signed_debit = SignedDebit {
+            actor_signature: keypair.sign(&try_serialize(&debit)?),
+            debit,
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: keypair.sign(&try_serialize(&credit)?),
+            credit,
+        };
+        let result = replica.validate(&signed_debit, &signed_credit);
+
+        assert!(matches!(result, Ok(Some(()))));
+        Ok(())
+    }
+
+    #[test]
+    fn verifies_with_cached_peer_key() -> Result<()> {
+        let sk_set = get_sk_set();
+        let keypair = get_keypair();
+        let id = keypair.public_key();
+        let replica =
+            get_replica_from_history(&sk_set, id, vec![get_propagated(&sk_set, id, 100)?])?;
+        assert_eq!(
+            replica.peer_key,
+            PublicKey::Bls(sk_set.public_keys().public_key())
+        );
+        let (signed_debit, signed_credit) = get_signed_transfer(&keypair, 0, 10, get_random_pk())?;
+        let proof = get_transfer_proof(&sk_set, signed_debit, signed_credit)?;
+        let (_, credit) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let credit_proof = get_credit_proof(&sk_set, credit)?;
+        let (_, forged) = get_signed_transfer(&get_keypair(), 0, 10, id)?;
+        let forged_proof = get_credit_proof(&get_sk_set(), forged)?;
+
+        assert!(replica.register(&proof, || Ok(get_random_pk()))?.is_some());
+        assert!(replica
+            .receive_propagated(&credit_proof, || Ok(get_random_pk()))?
+            .is_some());
+        assert!(matches!(
+            replica.receive_propagated(&forged_proof, || Ok(get_random_pk())),
+            Err(Error::InvalidSignature)
+        ));
+        Ok(())
+    }
+
+    fn get_replica_from_history(
+        sk_set: &SecretKeySet,
+        id: PublicKey,
+        events: Vec<ReplicaEvent>,
+    ) -> Result<WalletReplica> {
+        let peer_replicas = sk_set.public_keys();
+        WalletReplica::from_history(
+            id,
+            peer_replicas.public_key_share(0),
+            KeyIndex(0),
+            peer_replicas,
+            events,
+        )
+    }
+
+    fn get_validated(
+        sk_set: &SecretKeySet,
+        keypair: &Keypair,
+        counter: u64,
+        amount: u64,
+    ) -> Result<ReplicaEvent> {
+        let (signed_debit, signed_credit) =
+            get_signed_transfer(keypair, counter, amount, get_random_pk())?;
+        let secret_key = sk_set.secret_key_share(0);
+        Ok(ReplicaEvent::TransferValidated(TransferValidated {
+            replica_debit_sig: SignatureShare {
+                index: 0,
+                share: secret_key.sign(try_serialize(&signed_debit)?),
+            },
+            replica_credit_sig: SignatureShare {
+                index: 0,
+                share: secret_key.sign(try_serialize(&signed_credit)?),
+            },
+            signed_debit,
+            signed_credit,
+            replicas: sk_set.public_keys(),
+        }))
+    }
+
+    fn get_registered(
+        sk_set: &SecretKeySet,
+        keypair: &Keypair,
+        counter: u64,
+        amount: u64,
+    ) -> Result<ReplicaEvent> {
+        let (signed_debit, signed_credit) =
+            get_signed_transfer(keypair, counter, amount, get_random_pk())?;
+        let transfer_proof = get_transfer_proof(sk_set, signed_debit, signed_credit)?;
+        Ok(ReplicaEvent::TransferRegistered(TransferRegistered {
+            transfer_proof,
+        }))
+    }
+
+    fn get_propagated(
+        sk_set: &SecretKeySet,
+        recipient: PublicKey,
+        amount: u64,
+    ) -> Result<ReplicaEvent> {
+        let (_, signed_credit) = get_signed_transfer(&get_keypair(), 0, amount, recipient)?;
+        get_propagated_proof(sk_set, get_credit_proof(sk_set, signed_credit)?)
+    }
+
+    fn get_propagated_proof(
+        sk_set: &SecretKeySet,
+        credit_proof: CreditAgreementProof,
+    ) -> Result<ReplicaEvent> {
+        let crediting_replica_sig = SignatureShare {
+            index: 0,
+            share: sk_set
+                .secret_key_share(0)
+                .sign(try_serialize(&credit_proof)?),
+        };
+        Ok(ReplicaEvent::TransferPropagated(TransferPropagated {
+            credit_proof,
+            crediting_replica_sig,
+            crediting_replica_keys: PublicKey::Bls(sk_set.public_keys().public_key()),
+        }))
+    }
+
+    /// A genesis credit, signed by the group of the given secret key set.
+    fn get_genesis_proof(
+        sk_set: &SecretKeySet,
+        recipient: PublicKey,
+        amount: u64,
+    ) -> Result<CreditAgreementProof> {
+        let credit = Credit {
+            id: Default::default(),
+            recipient,
+            amount: Money::from_nano(amount),
+            msg: "genesis".to_string(),
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: sign_with_set(sk_set, &try_serialize(&credit)?)?,
+            credit,
+        };
+        get_credit_proof(sk_set, signed_credit)
+    }
+
+    fn get_signed_transfer(
+        keypair: &Keypair,
+        counter: u64,
+        amount: u64,
+        recipient: PublicKey,
+    ) -> Result<(SignedDebit, SignedCredit)> {
+        let debit = Debit {
+            id: Dot::new(keypair.public_key(), counter),
+            amount: Money::from_nano(amount),
+        };
+        let credit = Credit {
+            id: debit.credit_id()?,
+            recipient,
+            amount: debit.amount(),
+            msg: "asdf".to_string(),
+        };
+        let signed_debit = SignedDebit {
+            actor_signature: keypair.sign(&try_serialize(&debit)?),
+            debit,
+        };
+        let signed_credit = SignedCredit {
+            actor_signature: keypair.sign(&try_serialize(&credit)?),
+            credit,
+        };
+        Ok((signed_debit, signed_credit))
+    }
+
+    fn get_transfer_proof(
+        sk_set: &SecretKeySet,
+        signed_debit: SignedDebit,
+        signed_credit: SignedCredit,
+    ) -> Result<TransferAgreementProof> {
+        Ok(TransferAgreementProof {
+            debit_sig: sign_with_set(sk_set, &try_serialize(&signed_debit)?)?,
+            credit_sig: sign_with_set(sk_set, &try_serialize(&signed_credit)?)?,
+            signed_debit,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        })
+    }
+
+    fn get_credit_proof(
+        sk_set: &SecretKeySet,
+        signed_credit: SignedCredit,
+    ) -> Result<CreditAgreementProof> {
+        Ok(CreditAgreementProof {
+            debiting_replicas_sig: sign_with_set(sk_set, &try_serialize(&signed_credit)?)?,
+            signed_credit,
+            debiting_replicas_keys: sk_set.public_keys(),
+        })
+    }
+
+    /// A quorum of signature shares of the given secret key set.
+    fn get_shares(sk_set: &SecretKeySet, data: &[u8]) -> BTreeMap<usize, SignatureShare> {
+        (0..=sk_set.threshold())
+            .map(|index| {
+                let share = sk_set.secret_key_share(index).sign(data);
+                (index, SignatureShare { index, share })
+            })
+            .collect()
+    }
+
+    /// Combines a quorum of shares into the group signature.
+    fn sign_with_set(sk_set: &SecretKeySet, data: &[u8]) -> Result<Signature> {
+        let shares: BTreeMap<_, _> = (0..=sk_set.threshold())
+            .map(|i| (i, sk_set.secret_key_share(i).sign(data)))
+            .collect();
+        let signature = sk_set
+            .public_keys()
+            .combine_signatures(&shares)
+            .map_err(|e| Error::Unexpected(e.to_string()))?;
+        Ok(Signature::Bls(signature))
+    }
+
+    fn try_serialize<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|_| Error::from("serialization failed"))
+    }
+
+    fn get_sk_set() -> SecretKeySet {
+        SecretKeySet::random(1, &mut rand::thread_rng())
+    }
+
+    fn get_keypair() -> Keypair {
+        Keypair::new_ed25519(&mut rand::thread_rng())
+    }
+
+    fn get_random_pk() -> PublicKey {
+        PublicKey::from(SecretKey::random().public_key())
     }
 }